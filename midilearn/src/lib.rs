@@ -91,8 +91,8 @@ impl User for MidiLearn {
     /// Practice a single exercise.  Waits for the user to play the exercise, and then returns a
     /// status indicating how well it was played.  Next can be set to the possibly next problem,
     /// which will be shown after the current one.
-    fn single(&mut self, word: &Problem, next: Option<&Problem>) -> Result<Status> {
-        let st1 = self.single_once(word, next)?;
+    fn single(&mut self, word: &Problem, next: Option<&Problem>) -> timelearn::Result<Status> {
+        let st1 = self.single_once(word, next).map_err(timelearn::Error::User)?;
 
         let mut stn = st1;
         loop {
@@ -103,7 +103,7 @@ impl User for MidiLearn {
                 _ => (),
             }
             println!("** Mistakes made, please play again **");
-            stn = self.single_once(word, next)?;
+            stn = self.single_once(word, next).map_err(timelearn::Error::User)?;
         }
     }
 }
@@ -0,0 +1,923 @@
+//! Anki packages.
+//!
+//! An Anki package (`.apkg`, or `.colpkg` for a whole collection) is a zip file holding the
+//! collection, an sqlite database, as `collection.anki21` or `collection.anki2`.  Packages written
+//! by newer versions of Anki only hold a compressed `collection.anki21b`, which we can't read;
+//! those have to be exported from Anki with "Support older Anki versions" checked.
+//!
+//! When importing, each note becomes a problem, with its first field as the question, and its
+//! second as the answer.  Only the note's first card is used, so reversed cards are dropped.  The
+//! history is given to the default profile.
+//!
+//! When exporting, each problem becomes a note of the "Basic" note type, in a deck named after
+//! the database, and written using the older collection format, so that any version of Anki can
+//! import it.  Only the default profile's learning state and history are exported.  Problems in
+//! their learning or relearning steps become cards in learning, and the deck is given the
+//! database's steps, so Anki takes them through the rest of the same steps.  Anki can't schedule
+//! reviews less than a day apart, so problems being reviewed at shorter intervals also become
+//! cards in learning, with a single step left.
+
+use rusqlite::{Connection, Transaction};
+use serde_json;
+use sha1::Sha1;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use tempdir::TempDir;
+use timelearn::dump::{LearningEntry, LogEntry, ProblemEntry, Suspension};
+use timelearn::{Dump, Steps, Store, DEFAULT_PROFILE, INITIAL_EASE, MIN_INTERVAL, SCHEMA_VERSION};
+use zip::{ZipArchive, ZipWriter};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use {open_for_reading, Error, Result};
+
+// The names the collection can have within a package, in order of preference.
+static COLLECTION_NAMES: &'static [&'static str] = &["collection.anki21", "collection.anki2"];
+
+// The name of the collection written by newer versions of Anki.
+static COMPRESSED_NAME: &'static str = "collection.anki21b";
+
+// The number of seconds in a day.
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+// HTML tags that separate words.
+static BREAKING_TAGS: &'static [&'static str] = &["br", "div", "p", "li", "tr", "td"];
+
+// Anki's ease factor, in permille, that corresponds to `INITIAL_EASE`.
+const ANKI_EASE: f64 = 2500.0;
+
+/// Create a new "simple" database at `path` from the notes in the Anki package `package`.  If
+/// `history` is set, the scheduling and review history of the cards are imported as well,
+/// otherwise all of the problems start out unlearned.
+pub fn import_anki(package: &str, path: &str, history: bool) -> Result<()> {
+    let dir = TempDir::new("stenome")?;
+    let coll = dir.path().join("collection.anki2");
+    extract(package, &coll)?;
+
+    let dump = {
+        let conn = Connection::open(&coll)?;
+        read_collection(&conn, history)?
+    };
+    Store::import(path, &dump)?;
+    println!("Imported {} problems, {} reviews",
+             dump.problems.len(),
+             dump.log.len());
+    Ok(())
+}
+
+// Extract the collection from the package into the file `dest`.
+fn extract(package: &str, dest: &Path) -> Result<()> {
+    let mut zip = ZipArchive::new(File::open(package)?)?;
+    for name in COLLECTION_NAMES {
+        match zip.by_name(name) {
+            Ok(mut file) => {
+                io::copy(&mut file, &mut File::create(dest)?)?;
+                return Ok(());
+            }
+            Err(ZipError::FileNotFound) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if zip.by_name(COMPRESSED_NAME).is_ok() {
+        let msg = "package is from a newer Anki, export with \"Support older Anki versions\"";
+        Err(Error::Anki(msg.to_owned()))
+    } else {
+        Err(Error::Anki("package does not contain a collection".to_owned()))
+    }
+}
+
+// A card, with the fields we use.
+struct Card {
+    id: i64,
+    nid: i64,
+    typ: i64,
+    queue: i64,
+    due: i64,
+    ivl: i64,
+    factor: i64,
+    lapses: u32,
+    left: i64,
+}
+
+// Read the notes (and possibly the history) of the collection.
+fn read_collection(conn: &Connection, history: bool) -> Result<Dump> {
+    let crt: i64 = conn.query_row("SELECT crt FROM col", &[], |row| row.get(0))?;
+
+    // The first card of each note.
+    let mut cards = HashMap::new();
+    {
+        let mut stmt = conn.prepare("
+            SELECT id, nid, type, queue, due, ivl, factor, lapses, left
+            FROM cards
+            ORDER BY nid, ord")?;
+        let rows = stmt.query_map(&[], |row| {
+                Card {
+                    id: row.get(0),
+                    nid: row.get(1),
+                    typ: row.get(2),
+                    queue: row.get(3),
+                    due: row.get(4),
+                    ivl: row.get(5),
+                    factor: row.get(6),
+                    lapses: row.get(7),
+                    left: row.get(8),
+                }
+            })?;
+        for card in rows {
+            let card = card?;
+            cards.entry(card.nid).or_insert(card);
+        }
+    }
+
+    let mut problems = vec![];
+    let mut questions = HashSet::new();
+    let mut probids = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT id, tags, flds FROM notes ORDER BY id")?;
+        let rows = stmt.query_map(&[], |row| {
+                let id: i64 = row.get(0);
+                let tags: String = row.get(1);
+                let flds: String = row.get(2);
+                (id, tags, flds)
+            })?;
+        for row in rows {
+            let (nid, tags, flds) = row?;
+            let mut fields = flds.split('\x1f');
+            let question = clean(fields.next().unwrap_or(""));
+            let answer = clean(fields.next().unwrap_or(""));
+
+            // Questions must be unique, so only the first note with a given question is kept.
+            if question.is_empty() || !questions.insert(question.clone()) {
+                continue;
+            }
+
+            let id = problems.len() as i64 + 1;
+            let card = cards.get(&nid);
+            if let Some(card) = card {
+                probids.insert(card.id, id);
+            }
+            let mut tags: Vec<String> = tags.split_whitespace().map(|t| t.to_owned()).collect();
+            tags.sort();
+            tags.dedup();
+            problems.push(ProblemEntry {
+                              id: id,
+                              question: question,
+                              answer: answer,
+                              tags: tags,
+                              learning: if history {
+                                  card.and_then(|c| learning(c, crt)).into_iter().collect()
+                              } else {
+                                  vec![]
+                              },
+                              imported: vec![],
+                              leeches: vec![],
+                              suspended: match card {
+                                  Some(c) if c.queue == -1 => {
+                                      vec![Suspension {
+                                               profile: DEFAULT_PROFILE.to_owned(),
+                                               until: None,
+                                           }]
+                                  }
+                                  _ => vec![],
+                              },
+                          });
+        }
+    }
+
+    let mut log = vec![];
+    if history {
+        let mut stmt = conn.prepare("SELECT id, cid, ease FROM revlog ORDER BY id")?;
+        let rows = stmt.query_map(&[], |row| {
+                let id: i64 = row.get(0);
+                let cid: i64 = row.get(1);
+                let ease: i64 = row.get(2);
+                (id, cid, ease)
+            })?;
+        for row in rows {
+            let (id, cid, ease) = row?;
+            // Manual rescheduling is logged with an ease of 0.
+            if !(1..=4).contains(&ease) {
+                continue;
+            }
+            if let Some(&probid) = probids.get(&cid) {
+                log.push(LogEntry {
+                             stamp: id as f64 / 1000.0,
+                             score: ease as u8,
+                             probid: probid,
+                             profile: DEFAULT_PROFILE.to_owned(),
+                         });
+            }
+        }
+    }
+
+    Ok(Dump {
+           schema: SCHEMA_VERSION.to_owned(),
+           kind: "simple".to_owned(),
+           config: vec![],
+           profiles: vec![DEFAULT_PROFILE.to_owned()],
+           problems: problems,
+           log: log,
+       })
+}
+
+// Convert the scheduling of a card into learning state.  New cards have none.
+fn learning(card: &Card, crt: i64) -> Option<LearningEntry> {
+    if card.typ == 0 {
+        return None;
+    }
+
+    // Cards in learning are due at a timestamp, and review cards on a day number, counted from
+    // the creation of the collection.
+    let next = if card.due > 1000000000 {
+        card.due as f64
+    } else {
+        (crt + card.due * DAY as i64) as f64
+    };
+
+    // Positive intervals are in days, and negative ones in seconds.
+    let interval = if card.ivl > 0 {
+        card.ivl as f64 * DAY
+    } else if card.ivl < 0 {
+        -card.ivl as f64
+    } else {
+        MIN_INTERVAL
+    };
+
+    let ease = if card.factor > 0 {
+        card.factor as f64 / ANKI_EASE * INITIAL_EASE
+    } else {
+        INITIAL_EASE
+    };
+
+    // Anki's learning steps don't carry over, so cards that are still learning are scheduled
+    // normally.
+    Some(LearningEntry {
+             profile: DEFAULT_PROFILE.to_owned(),
+             next: next,
+             interval: interval.max(MIN_INTERVAL),
+             ease: ease,
+             lapses: card.lapses,
+             phase: "review".to_owned(),
+             step: 0,
+             graduate: None,
+         })
+}
+
+// Convert the HTML of a field into plain text.  Tags that break lines become spaces.
+fn clean(html: &str) -> String {
+    let mut text = String::new();
+    let mut tag = None;
+    for ch in html.chars() {
+        match (ch, tag.take()) {
+            ('<', None) => tag = Some(String::new()),
+            ('>', Some(name)) => {
+                let name = name.trim_matches('/').to_lowercase();
+                if BREAKING_TAGS.contains(&name.split_whitespace().next().unwrap_or("")) {
+                    text.push(' ');
+                }
+            }
+            (ch, Some(mut name)) => {
+                name.push(ch);
+                tag = Some(name);
+            }
+            (ch, None) => text.push(ch),
+        }
+    }
+    let text = text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Write the database at `path` to the Anki package `package`.  The learning state of the
+/// problems is carried over as the scheduling of the cards, and the log as the review history.
+pub fn export_anki(path: &str, package: &str) -> Result<()> {
+    let st = open_for_reading(path)?;
+    let mut dump = st.export()?;
+    // Anki has no notion of profiles, so only keep the default one.
+    for prob in &mut dump.problems {
+        prob.learning.retain(|l| l.profile == DEFAULT_PROFILE);
+        prob.suspended.retain(|s| s.profile == DEFAULT_PROFILE);
+    }
+    dump.log.retain(|ent| ent.profile == DEFAULT_PROFILE);
+    let deck = match Path::new(path).file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => "stenome".to_owned(),
+    };
+
+    let dir = TempDir::new("stenome")?;
+    let coll = dir.path().join("collection.anki2");
+    {
+        let mut conn = Connection::open(&coll)?;
+        let tx = conn.transaction()?;
+        write_collection(&tx, &dump, &deck, st.get_steps(), st.now())?;
+        tx.commit()?;
+    }
+
+    let mut zip = ZipWriter::new(File::create(package)?);
+    zip.start_file("collection.anki2", FileOptions::default())?;
+    io::copy(&mut File::open(&coll)?, &mut zip)?;
+    // The package has no media.
+    zip.start_file("media", FileOptions::default())?;
+    zip.write_all(b"{}")?;
+    zip.finish()?;
+    println!("Exported {} notes, {} reviews",
+             dump.problems.len(),
+             dump.log.len());
+    Ok(())
+}
+
+// The collection schema, version 11, which all versions of Anki can import.
+static SCHEMA: &'static str = "
+    CREATE TABLE col (id INTEGER PRIMARY KEY, crt INTEGER NOT NULL, mod INTEGER NOT NULL,
+        scm INTEGER NOT NULL, ver INTEGER NOT NULL, dty INTEGER NOT NULL, usn INTEGER NOT NULL,
+        ls INTEGER NOT NULL, conf TEXT NOT NULL, models TEXT NOT NULL, decks TEXT NOT NULL,
+        dconf TEXT NOT NULL, tags TEXT NOT NULL);
+    CREATE TABLE notes (id INTEGER PRIMARY KEY, guid TEXT NOT NULL, mid INTEGER NOT NULL,
+        mod INTEGER NOT NULL, usn INTEGER NOT NULL, tags TEXT NOT NULL, flds TEXT NOT NULL,
+        sfld INTEGER NOT NULL, csum INTEGER NOT NULL, flags INTEGER NOT NULL,
+        data TEXT NOT NULL);
+    CREATE TABLE cards (id INTEGER PRIMARY KEY, nid INTEGER NOT NULL, did INTEGER NOT NULL,
+        ord INTEGER NOT NULL, mod INTEGER NOT NULL, usn INTEGER NOT NULL, type INTEGER NOT NULL,
+        queue INTEGER NOT NULL, due INTEGER NOT NULL, ivl INTEGER NOT NULL,
+        factor INTEGER NOT NULL, reps INTEGER NOT NULL, lapses INTEGER NOT NULL,
+        left INTEGER NOT NULL, odue INTEGER NOT NULL, odid INTEGER NOT NULL,
+        flags INTEGER NOT NULL, data TEXT NOT NULL);
+    CREATE TABLE revlog (id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, usn INTEGER NOT NULL,
+        ease INTEGER NOT NULL, ivl INTEGER NOT NULL, lastIvl INTEGER NOT NULL,
+        factor INTEGER NOT NULL, time INTEGER NOT NULL, type INTEGER NOT NULL);
+    CREATE TABLE graves (usn INTEGER NOT NULL, oid INTEGER NOT NULL, type INTEGER NOT NULL);
+    CREATE INDEX ix_notes_usn ON notes (usn);
+    CREATE INDEX ix_cards_usn ON cards (usn);
+    CREATE INDEX ix_revlog_usn ON revlog (usn);
+    CREATE INDEX ix_cards_nid ON cards (nid);
+    CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+    CREATE INDEX ix_revlog_cid ON revlog (cid);
+    CREATE INDEX ix_notes_csum ON notes (csum);";
+
+// The ids of the note type and deck written.
+const MODEL_ID: i64 = 1342697561419;
+const DECK_ID: i64 = 1500000000000;
+
+// The lowest ease factor that Anki allows.
+const ANKI_MIN_EASE: f64 = 1300.0;
+
+// Write the contents of the dump into a new, empty, collection, with the learning steps `steps`.
+fn write_collection(tx: &Transaction,
+                    dump: &Dump,
+                    deck: &str,
+                    steps: &Steps,
+                    now: f64)
+                    -> Result<()> {
+    tx.execute_batch(SCHEMA)?;
+
+    // Due dates of review cards are counted in days from the creation of the collection, so
+    // start it on the day of the earliest date needed.
+    let earliest = dump.problems
+        .iter()
+        .filter_map(|p| p.learning.first().map(|l| l.next))
+        .fold(now, f64::min);
+    let crt = (earliest / DAY).floor() * DAY;
+    let mod_ms = (now * 1000.0) as i64;
+
+    tx.execute("INSERT INTO col VALUES (1, ?, ?, ?, 11, 0, 0, 0, ?, ?, ?, ?, '{}')",
+               &[&(crt as i64),
+                 &mod_ms,
+                 &mod_ms,
+                 &collection_conf(dump.problems.len()).to_string(),
+                 &models().to_string(),
+                 &decks(deck).to_string(),
+                 &deck_conf(steps).to_string()])?;
+
+    let mut reps = HashMap::new();
+    for ent in &dump.log {
+        *reps.entry(ent.probid).or_insert(0) += 1;
+    }
+
+    let mut factors = HashMap::new();
+    for (pos, prob) in dump.problems.iter().enumerate() {
+        let tags = if prob.tags.is_empty() {
+            String::new()
+        } else {
+            format!(" {} ", prob.tags.join(" "))
+        };
+        let digest = Sha1::from(prob.question.as_bytes()).digest().bytes();
+        let guid: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        let csum = digest[..4].iter().fold(0i64, |acc, &b| acc << 8 | b as i64);
+        let flds = format!("{}\x1f{}", escape(&prob.question), escape(&prob.answer));
+        tx.execute("INSERT INTO notes VALUES (?, ?, ?, ?, 0, ?, ?, ?, ?, 0, '')",
+                   &[&(mod_ms + prob.id),
+                     &guid,
+                     &MODEL_ID,
+                     &(now as i64),
+                     &tags,
+                     &flds,
+                     &prob.question,
+                     &csum])?;
+
+        let card = card(prob, pos as i64 + 1, crt, steps, now);
+        factors.insert(prob.id, card.factor);
+        tx.execute("INSERT INTO cards VALUES (?, ?, ?, 0, ?, 0, ?, ?, ?, ?, ?, ?, ?, ?, 0, 0, 0, \
+                    '')",
+                   &[&(mod_ms + prob.id),
+                     &(mod_ms + prob.id),
+                     &DECK_ID,
+                     &(now as i64),
+                     &card.typ,
+                     &card.queue,
+                     &card.due,
+                     &card.ivl,
+                     &card.factor,
+                     &reps.get(&prob.id).cloned().unwrap_or(0i64),
+                     &card.lapses,
+                     &card.left])?;
+    }
+
+    // The revlog is keyed by the time of the review, in milliseconds, so make sure those are
+    // unique.
+    let mut last = 0;
+    let mut seen = HashSet::new();
+    for ent in &dump.log {
+        let id = ((ent.stamp * 1000.0) as i64).max(last + 1);
+        last = id;
+        // The first review of a card is when it was learned.
+        let typ = if seen.insert(ent.probid) { 0 } else { 1 };
+        tx.execute("INSERT INTO revlog VALUES (?, ?, 0, ?, 0, 0, ?, 0, ?)",
+                   &[&id,
+                     &(mod_ms + ent.probid),
+                     &ent.score,
+                     &factors.get(&ent.probid).cloned().unwrap_or(0),
+                     &typ])?;
+    }
+
+    Ok(())
+}
+
+// Convert the learning state of a problem into the scheduling of its card.  `pos` is the
+// position of new cards in the new card queue.
+fn card(prob: &ProblemEntry, pos: i64, crt: f64, steps: &Steps, now: f64) -> Card {
+    let mut card = Card {
+        id: 0,
+        nid: 0,
+        typ: 0,
+        queue: 0,
+        due: pos,
+        ivl: 0,
+        factor: 0,
+        lapses: 0,
+        left: 0,
+    };
+
+    if let Some(learn) = prob.learning.first() {
+        card.factor = (learn.ease / INITIAL_EASE * ANKI_EASE).max(ANKI_MIN_EASE).round() as i64;
+        card.lapses = learn.lapses;
+        match learn.phase.as_str() {
+            "learning" => {
+                card.typ = 1;
+                card.queue = 1;
+                card.due = learn.next as i64;
+                card.left = steps_left(&steps.learning, learn.step);
+            }
+            "relearning" => {
+                // Relearning cards keep the interval they will return to.
+                card.typ = 2;
+                card.queue = 1;
+                card.due = learn.next as i64;
+                card.ivl = (learn.graduate.unwrap_or(DAY) / DAY).round().max(1.0) as i64;
+                card.left = steps_left(&steps.relearning, learn.step);
+            }
+            _ if learn.interval < DAY => {
+                // Anki can't review a card again within a day, so treat it as having one step
+                // left.
+                card.typ = 1;
+                card.queue = 1;
+                card.due = learn.next as i64;
+                card.left = 1001;
+            }
+            _ => {
+                card.typ = 2;
+                card.queue = 2;
+                card.due = ((learn.next - crt) / DAY).floor() as i64;
+                card.ivl = (learn.interval / DAY).round() as i64;
+            }
+        }
+    }
+
+    match prob.suspended.first() {
+        Some(&Suspension { until: None, .. }) => card.queue = -1,
+        Some(&Suspension { until: Some(until), .. }) if until > now => card.queue = -2,
+        _ => (),
+    }
+
+    card
+}
+
+// Anki's count of the steps a card in learning has left, given the steps and the index of the
+// current one: the steps left today, times 1000, plus the steps left in all.  The steps are taken
+// to all fall today, and there is always at least one left.
+fn steps_left(steps: &[f64], step: u32) -> i64 {
+    let left = steps.len().saturating_sub(step as usize).max(1) as i64;
+    left * 1000 + left
+}
+
+// Escape text for use in a field, which holds HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// The collection's configuration.
+fn collection_conf(count: usize) -> serde_json::Value {
+    json!({
+        "nextPos": count + 1,
+        "estTimes": true,
+        "activeDecks": [DECK_ID],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": DECK_ID,
+        "newBury": true,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": MODEL_ID.to_string(),
+        "collapseTime": 1200
+    })
+}
+
+// The "Basic" note type, with a front and a back.
+fn models() -> serde_json::Value {
+    let field = |name: &str, ord: usize| {
+        json!({
+            "name": name,
+            "ord": ord,
+            "sticky": false,
+            "rtl": false,
+            "font": "Arial",
+            "size": 20,
+            "media": []
+        })
+    };
+    let mut models = serde_json::Map::new();
+    models.insert(MODEL_ID.to_string(),
+                  json!({
+        "id": MODEL_ID,
+        "name": "Basic",
+        "type": 0,
+        "mod": 0,
+        "usn": 0,
+        "sortf": 0,
+        "did": DECK_ID,
+        "tmpls": [{
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": "{{Front}}",
+            "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}",
+            "did": null,
+            "bqfmt": "",
+            "bafmt": ""
+        }],
+        "flds": [field("Front", 0), field("Back", 1)],
+        "css": ".card {\n font-family: arial;\n font-size: 20px;\n text-align: center;\n}\n",
+        "latexPre": "\\documentclass[12pt]{article}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "tags": [],
+        "vers": [],
+        "req": [[0, "any", [0]]]
+    }));
+    serde_json::Value::Object(models)
+}
+
+// The default deck, which Anki requires, and the deck holding the cards.
+fn decks(name: &str) -> serde_json::Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "mod": 0,
+            "usn": 0,
+            "lrnToday": [0, 0],
+            "revToday": [0, 0],
+            "newToday": [0, 0],
+            "timeToday": [0, 0],
+            "collapsed": false,
+            "desc": "",
+            "dyn": 0,
+            "conf": 1,
+            "extendNew": 10,
+            "extendRev": 50
+        })
+    };
+    let mut decks = serde_json::Map::new();
+    decks.insert("1".to_owned(), deck(1, "Default"));
+    decks.insert(DECK_ID.to_string(), deck(DECK_ID, name));
+    serde_json::Value::Object(decks)
+}
+
+// Anki's steps, in minutes, for the given steps in seconds, or the default ones if there are none.
+fn delays(steps: &[f64], default: &[f64]) -> Vec<f64> {
+    if steps.is_empty() {
+        default.to_vec()
+    } else {
+        steps.iter().map(|s| s / 60.0).collect()
+    }
+}
+
+// The default deck options, with the learning steps `steps`.
+fn deck_conf(steps: &Steps) -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "bury": true,
+                "delays": delays(&steps.learning, &[1.0, 10.0]),
+                "initialFactor": 2500,
+                "ints": [1, 4, 7],
+                "order": 1,
+                "perDay": 20,
+                "separate": true
+            },
+            "lapse": {
+                "delays": delays(&steps.relearning, &[10.0]),
+                "leechAction": 0,
+                "leechFails": 8,
+                "minInt": 1,
+                "mult": 0
+            },
+            "rev": {
+                "bury": true,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "ivlFct": 1,
+                "maxIvl": 36500,
+                "minSpace": 1,
+                "perDay": 100
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The creation time of the test collection.
+    const CRT: i64 = 1500000000;
+
+    // Build a collection with a new card, a review card, a card in learning, a suspended card, and
+    // a second note with the same question as the first.
+    fn collection(conn: &Connection) {
+        conn.execute_batch(SCHEMA).unwrap();
+        conn.execute("INSERT INTO col VALUES (1, ?, 0, 0, 11, 0, 0, 0, '{}', '{}', '{}', '{}', \
+                      '{}')",
+                     &[&CRT])
+            .unwrap();
+        let notes: &[(i64, &str, &str)] = &[(1, " verb b ", "<b>go</b>\x1fto&nbsp;move"),
+                                            (2, "", "ran\x1fpast<br>of run"),
+                                            (3, "", "sat\x1fpast of sit"),
+                                            (4, "", "set\x1fto place"),
+                                            (5, "", "<i>go</i>\x1fagain")];
+        for &(id, tags, flds) in notes {
+            conn.execute("INSERT INTO notes VALUES (?, '', 1, 0, 0, ?, ?, '', 0, 0, '')",
+                         &[&id, &tags, &flds])
+                .unwrap();
+        }
+        // The id, type, queue, due, ivl, factor and lapses of the card of each note.
+        let cards: &[(i64, i64, i64, i64, i64, i64, i64)] = &[(1, 0, 0, 1, 0, 0, 0),
+                                                              (2, 2, 2, 10, 5, 2000, 2),
+                                                              (3, 1, 1, CRT + 600, -60, 0, 0),
+                                                              (4, 2, -1, 3, 2, 2500, 0),
+                                                              (5, 2, 2, 4, 3, 2500, 0)];
+        for &(nid, typ, queue, due, ivl, factor, lapses) in cards {
+            conn.execute("INSERT INTO cards VALUES (?, ?, 1, 0, 0, 0, ?, ?, ?, ?, ?, 0, ?, 0, 0, \
+                          0, 0, '')",
+                         &[&(nid + 10), &nid, &typ, &queue, &due, &ivl, &factor, &lapses])
+                .unwrap();
+        }
+        // A review, a manual rescheduling, a review of the dropped note, and a lapse.
+        let revlog: &[(i64, i64, i64)] = &[(CRT * 1000 + 123, 12, 3),
+                                           (CRT * 1000 + 1000, 12, 0),
+                                           (CRT * 1000 + 2000, 15, 4),
+                                           (CRT * 1000 + 3000, 13, 1)];
+        for &(id, cid, ease) in revlog {
+            conn.execute("INSERT INTO revlog VALUES (?, ?, 0, ?, 0, 0, 0, 0, 1)",
+                         &[&id, &cid, &ease])
+                .unwrap();
+        }
+    }
+
+    fn reviewing(next: f64, interval: f64, ease: f64, lapses: u32) -> Vec<LearningEntry> {
+        vec![LearningEntry {
+                 profile: DEFAULT_PROFILE.to_owned(),
+                 next: next,
+                 interval: interval,
+                 ease: ease,
+                 lapses: lapses,
+                 phase: "review".to_owned(),
+                 step: 0,
+                 graduate: None,
+             }]
+    }
+
+    fn problem(id: i64, question: &str, learning: Vec<LearningEntry>) -> ProblemEntry {
+        ProblemEntry {
+            id: id,
+            question: question.to_owned(),
+            answer: format!("answer {}", id),
+            tags: vec![],
+            learning: learning,
+            imported: vec![],
+            leeches: vec![],
+            suspended: vec![],
+        }
+    }
+
+    // A problem in its steps.
+    fn stepping(next: f64, phase: &str, step: u32, graduate: Option<f64>) -> Vec<LearningEntry> {
+        let mut result = reviewing(next, 60.0, INITIAL_EASE, 0);
+        result[0].phase = phase.to_owned();
+        result[0].step = step;
+        result[0].graduate = graduate;
+        result
+    }
+
+    // Notes should become problems, with their cards' scheduling and review history.
+    #[test]
+    fn import() {
+        let tmp_dir = TempDir::new("anki").unwrap();
+        let conn = Connection::open(tmp_dir.path().join("collection.anki2")).unwrap();
+        collection(&conn);
+
+        let dump = read_collection(&conn, true).unwrap();
+        let questions: Vec<_> = dump.problems.iter().map(|p| p.question.as_str()).collect();
+        assert_eq!(questions, vec!["go", "ran", "sat", "set"]);
+        assert_eq!(dump.problems[0].answer, "to move");
+        assert_eq!(dump.problems[0].tags, vec!["b", "verb"]);
+        assert_eq!(dump.problems[1].answer, "past of run");
+
+        // Review cards are due on a day counted from the creation of the collection, and cards
+        // in learning at a time.
+        assert_eq!(dump.problems[0].learning, vec![]);
+        assert_eq!(dump.problems[1].learning,
+                   reviewing(CRT as f64 + 10.0 * DAY, 5.0 * DAY, 0.8 * INITIAL_EASE, 2));
+        assert_eq!(dump.problems[2].learning,
+                   reviewing(CRT as f64 + 600.0, 60.0, INITIAL_EASE, 0));
+        assert_eq!(dump.problems[3].learning,
+                   reviewing(CRT as f64 + 3.0 * DAY, 2.0 * DAY, INITIAL_EASE, 0));
+
+        assert_eq!(dump.problems[2].suspended, vec![]);
+        assert_eq!(dump.problems[3].suspended,
+                   vec![Suspension {
+                            profile: DEFAULT_PROFILE.to_owned(),
+                            until: None,
+                        }]);
+
+        assert_eq!(dump.log,
+                   vec![LogEntry {
+                            stamp: (CRT * 1000 + 123) as f64 / 1000.0,
+                            score: 3,
+                            probid: 2,
+                            profile: DEFAULT_PROFILE.to_owned(),
+                        },
+                        LogEntry {
+                            stamp: CRT as f64 + 3.0,
+                            score: 1,
+                            probid: 3,
+                            profile: DEFAULT_PROFILE.to_owned(),
+                        }]);
+
+        // Without the history, everything starts out unlearned.
+        let dump = read_collection(&conn, false).unwrap();
+        assert_eq!(dump.problems.len(), 4);
+        assert!(dump.problems.iter().all(|p| p.learning.is_empty()));
+        assert_eq!(dump.log, vec![]);
+    }
+
+    // A card in learning with no interval yet should get the shortest one.
+    #[test]
+    fn import_unstarted() {
+        let tmp_dir = TempDir::new("anki").unwrap();
+        let conn = Connection::open(tmp_dir.path().join("collection.anki2")).unwrap();
+        collection(&conn);
+        conn.execute("UPDATE cards SET ivl = 0 WHERE nid = 3", &[]).unwrap();
+
+        let dump = read_collection(&conn, true).unwrap();
+        assert_eq!(dump.problems[2].learning[0].interval, MIN_INTERVAL);
+    }
+
+    // HTML should be reduced to its text, with line breaks as spaces.
+    #[test]
+    fn clean_html() {
+        assert_eq!(clean("plain"), "plain");
+        assert_eq!(clean("<div>one</div><div>two</div>"), "one two");
+        assert_eq!(clean("one<br/>two<BR>three"), "one two three");
+        assert_eq!(clean("<b>bo</b>ld"), "bold");
+        assert_eq!(clean("<span style=\"x\">a &lt;b&gt; &amp;&nbsp;c</span>"), "a <b> & c");
+        assert_eq!(clean("  spaced \n out  "), "spaced out");
+    }
+
+    // Exporting and importing again should keep the problems, the scheduling of those being
+    // reviewed, and the history.  Problems in their steps become cards in learning.
+    #[test]
+    fn export() {
+        let now = CRT as f64;
+        let day = (now / DAY).floor() * DAY;
+        let mut dump = Dump {
+            schema: SCHEMA_VERSION.to_owned(),
+            kind: "simple".to_owned(),
+            config: vec![],
+            profiles: vec![DEFAULT_PROFILE.to_owned()],
+            problems: vec![problem(1, "a < b", reviewing(day + DAY, 4.0 * DAY, 1.2, 1)),
+                           problem(2, "new", vec![]),
+                           problem(3, "learning", stepping(now + 600.0, "learning", 1, None)),
+                           problem(4,
+                                   "relearning",
+                                   stepping(now + 600.0, "relearning", 0, Some(3.0 * DAY))),
+                           problem(5, "short", reviewing(now + 300.0, 300.0, INITIAL_EASE, 0)),
+                           problem(6, "suspended", reviewing(day + 2.0 * DAY, 2.0 * DAY, 1.0, 0))],
+            log: vec![LogEntry {
+                          stamp: now - 1000.5,
+                          score: 3,
+                          probid: 1,
+                          profile: DEFAULT_PROFILE.to_owned(),
+                      },
+                      LogEntry {
+                          stamp: now - 900.25,
+                          score: 1,
+                          probid: 1,
+                          profile: DEFAULT_PROFILE.to_owned(),
+                      }],
+        };
+        dump.problems[0].answer = "x & y".to_owned();
+        dump.problems[0].tags = vec!["a".to_owned(), "b".to_owned()];
+        dump.problems[5].suspended = vec![Suspension {
+                                              profile: DEFAULT_PROFILE.to_owned(),
+                                              until: None,
+                                          }];
+        let steps = Steps {
+            learning: vec![60.0, 600.0, 3600.0],
+            relearning: vec![600.0],
+        };
+
+        let tmp_dir = TempDir::new("anki").unwrap();
+        let mut conn = Connection::open(tmp_dir.path().join("collection.anki2")).unwrap();
+        {
+            let tx = conn.transaction().unwrap();
+            write_collection(&tx, &dump, "deck", &steps, now).unwrap();
+            tx.commit().unwrap();
+        }
+
+        // The fields are HTML, and the note is identified by a hash of its question.
+        let (guid, flds, csum): (String, String, i64) =
+            conn.query_row("SELECT guid, flds, csum FROM notes ORDER BY id LIMIT 1",
+                           &[],
+                           |row| (row.get(0), row.get(1), row.get(2)))
+                .unwrap();
+        assert_eq!(guid, "c7bad0befbc8e0cc");
+        assert_eq!(flds, "a &lt; b\x1fx &amp; y");
+        assert_eq!(csum, 3350909118);
+
+        let mut stmt = conn.prepare("SELECT type, queue, ivl, left, reps FROM cards ORDER BY nid")
+            .unwrap();
+        let cards: Vec<(i64, i64, i64, i64, i64)> =
+            stmt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect();
+        assert_eq!(cards,
+                   vec![(2, 2, 4, 0, 2),
+                        (0, 0, 0, 0, 0),
+                        (1, 1, 0, 2002, 0),
+                        (2, 1, 3, 1001, 0),
+                        (1, 1, 0, 1001, 0),
+                        (2, -1, 2, 0, 0)]);
+
+        let dconf: String = conn.query_row("SELECT dconf FROM col", &[], |row| row.get(0))
+            .unwrap();
+        let dconf: serde_json::Value = serde_json::from_str(&dconf).unwrap();
+        assert_eq!(dconf["1"]["new"]["delays"], json!([1.0, 10.0, 60.0]));
+        assert_eq!(dconf["1"]["lapse"]["delays"], json!([10.0]));
+
+        // Cards being reviewed come back as they were.
+        let back = read_collection(&conn, true).unwrap();
+        for (prob, orig) in back.problems.iter().zip(&dump.problems) {
+            assert_eq!((prob.id, &prob.question, &prob.answer, &prob.tags, &prob.suspended),
+                       (orig.id, &orig.question, &orig.answer, &orig.tags, &orig.suspended));
+        }
+        assert_eq!(back.problems.len(), dump.problems.len());
+        assert_eq!(back.problems[0].learning, dump.problems[0].learning);
+        assert_eq!(back.problems[1].learning, vec![]);
+        assert_eq!(back.problems[5].learning, dump.problems[5].learning);
+        assert_eq!(back.log, dump.log);
+    }
+}
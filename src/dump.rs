@@ -0,0 +1,344 @@
+//! Export and import of databases.
+//!
+//! A database can be written either as JSON, in the format described in `timelearn::dump`, or as
+//! a flat CSV file.  The CSV file has a header row, and then a row for each item in the database,
+//! where the `record` column says which kind of item it is, and decides which of the other
+//! columns are used:
+//!
+//! - `schema`: `value` is the schema version.
+//! - `kind`: `value` is the kind of problems in the database.
+//! - `config`: `key` and `value` are a config setting.
+//! - `profile`: `value` is the name of a profile.
+//! - `problem`: `id`, `question`, `answer`, and `tags` (separated by spaces) describe a problem.
+//! - `learning`: `next`, `interval`, `ease`, `lapses`, `phase`, `step` and `graduate` are the
+//!   learning state of the problem `id` for the profile `profile`.
+//! - `imported`: `next` and `interval` are the learning state that the problem `id` was imported
+//!   with for the profile `profile`.
+//! - `leech`: the problem `id` has been detected as a leech by the profile `profile`.
+//! - `suspended`: the problem `id` is suspended or buried by the profile `profile`, and `until`
+//!   is the time that a buried problem returns.
+//! - `log`: `id`, `profile`, `stamp` and `score` are a single answer from the log.
+//!
+//! The `learning`, `imported`, `leech` and `suspended` records of a problem follow its `problem`
+//! record.
+//!
+//! The format is chosen by the file name: names ending in `.csv` are CSV, and anything else is
+//! JSON.
+
+use csv;
+use serde_json;
+use std::fs::File;
+use timelearn::dump::{ConfigEntry, ImportedEntry, LearningEntry, LogEntry, ProblemEntry,
+                      Suspension};
+use timelearn::{Dump, Store};
+use {open_for_reading, Error, Result};
+
+/// Write the database at `path` to the file `out`.
+pub fn export(path: &str, out: &str) -> Result<()> {
+    let st = open_for_reading(path)?;
+    let dump = st.export()?;
+    if is_csv(out) {
+        write_csv(&dump, out)
+    } else {
+        serde_json::to_writer_pretty(File::create(out)?, &dump)?;
+        Ok(())
+    }
+}
+
+/// Create a new database at `path` from the file `input`, which was written by `export`.
+pub fn import(input: &str, path: &str) -> Result<()> {
+    let dump = if is_csv(input) {
+        read_csv(input)?
+    } else {
+        serde_json::from_reader(File::open(input)?)?
+    };
+    Store::import(path, &dump)?;
+    Ok(())
+}
+
+fn is_csv(name: &str) -> bool {
+    name.ends_with(".csv")
+}
+
+// A single row of the CSV file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Row {
+    record: String,
+    key: Option<String>,
+    value: Option<String>,
+    id: Option<i64>,
+    profile: Option<String>,
+    question: Option<String>,
+    answer: Option<String>,
+    tags: Option<String>,
+    next: Option<f64>,
+    interval: Option<f64>,
+    ease: Option<f64>,
+    lapses: Option<u32>,
+    phase: Option<String>,
+    step: Option<u32>,
+    graduate: Option<f64>,
+    until: Option<f64>,
+    stamp: Option<f64>,
+    score: Option<u8>,
+}
+
+fn write_csv(dump: &Dump, out: &str) -> Result<()> {
+    let mut wr = csv::Writer::from_path(out)?;
+    wr.serialize(Row {
+                     record: "schema".to_owned(),
+                     value: Some(dump.schema.clone()),
+                     ..Row::default()
+                 })?;
+    wr.serialize(Row {
+                     record: "kind".to_owned(),
+                     value: Some(dump.kind.clone()),
+                     ..Row::default()
+                 })?;
+    for ent in &dump.config {
+        wr.serialize(Row {
+                         record: "config".to_owned(),
+                         key: Some(ent.key.clone()),
+                         value: Some(ent.value.clone()),
+                         ..Row::default()
+                     })?;
+    }
+    for name in &dump.profiles {
+        wr.serialize(Row {
+                         record: "profile".to_owned(),
+                         value: Some(name.clone()),
+                         ..Row::default()
+                     })?;
+    }
+    for prob in &dump.problems {
+        wr.serialize(Row {
+                         record: "problem".to_owned(),
+                         id: Some(prob.id),
+                         question: Some(prob.question.clone()),
+                         answer: Some(prob.answer.clone()),
+                         tags: Some(prob.tags.join(" ")),
+                         ..Row::default()
+                     })?;
+        for learn in &prob.learning {
+            wr.serialize(Row {
+                             record: "learning".to_owned(),
+                             id: Some(prob.id),
+                             profile: Some(learn.profile.clone()),
+                             next: Some(learn.next),
+                             interval: Some(learn.interval),
+                             ease: Some(learn.ease),
+                             lapses: Some(learn.lapses),
+                             phase: Some(learn.phase.clone()),
+                             step: Some(learn.step),
+                             graduate: learn.graduate,
+                             ..Row::default()
+                         })?;
+        }
+        for imp in &prob.imported {
+            wr.serialize(Row {
+                             record: "imported".to_owned(),
+                             id: Some(prob.id),
+                             profile: Some(imp.profile.clone()),
+                             next: Some(imp.next),
+                             interval: Some(imp.interval),
+                             ..Row::default()
+                         })?;
+        }
+        for profile in &prob.leeches {
+            wr.serialize(Row {
+                             record: "leech".to_owned(),
+                             id: Some(prob.id),
+                             profile: Some(profile.clone()),
+                             ..Row::default()
+                         })?;
+        }
+        for susp in &prob.suspended {
+            wr.serialize(Row {
+                             record: "suspended".to_owned(),
+                             id: Some(prob.id),
+                             profile: Some(susp.profile.clone()),
+                             until: susp.until,
+                             ..Row::default()
+                         })?;
+        }
+    }
+    for ent in &dump.log {
+        wr.serialize(Row {
+                         record: "log".to_owned(),
+                         id: Some(ent.probid),
+                         profile: Some(ent.profile.clone()),
+                         stamp: Some(ent.stamp),
+                         score: Some(ent.score),
+                         ..Row::default()
+                     })?;
+    }
+    wr.flush()?;
+    Ok(())
+}
+
+fn read_csv(input: &str) -> Result<Dump> {
+    let mut dump = Dump {
+        schema: String::new(),
+        kind: String::new(),
+        config: vec![],
+        profiles: vec![],
+        problems: vec![],
+        log: vec![],
+    };
+
+    let mut rd = csv::Reader::from_path(input)?;
+    for row in rd.deserialize() {
+        let row: Row = row?;
+        match row.record.as_str() {
+            "schema" => dump.schema = field(row.value, "value")?,
+            "kind" => dump.kind = field(row.value, "value")?,
+            "config" => {
+                // Settings may be empty, such as an empty list of steps.
+                dump.config.push(ConfigEntry {
+                                     key: field(row.key, "key")?,
+                                     value: row.value.unwrap_or_default(),
+                                 })
+            }
+            "profile" => dump.profiles.push(field(row.value, "value")?),
+            "problem" => {
+                // Empty fields read as `None`, but the question and answer may be empty.
+                let tags = row.tags.unwrap_or_default();
+                dump.problems.push(ProblemEntry {
+                                       id: field(row.id, "id")?,
+                                       question: row.question.unwrap_or_default(),
+                                       answer: row.answer.unwrap_or_default(),
+                                       tags: tags.split_whitespace()
+                                           .map(|t| t.to_owned())
+                                           .collect(),
+                                       learning: vec![],
+                                       imported: vec![],
+                                       leeches: vec![],
+                                       suspended: vec![],
+                                   })
+            }
+            "learning" => {
+                let learn = LearningEntry {
+                    profile: field(row.profile, "profile")?,
+                    next: field(row.next, "next")?,
+                    interval: field(row.interval, "interval")?,
+                    ease: field(row.ease, "ease")?,
+                    lapses: field(row.lapses, "lapses")?,
+                    phase: field(row.phase, "phase")?,
+                    step: field(row.step, "step")?,
+                    graduate: row.graduate,
+                };
+                problem(&mut dump, row.id)?.learning.push(learn);
+            }
+            "imported" => {
+                let imp = ImportedEntry {
+                    profile: field(row.profile, "profile")?,
+                    next: field(row.next, "next")?,
+                    interval: field(row.interval, "interval")?,
+                };
+                problem(&mut dump, row.id)?.imported.push(imp);
+            }
+            "leech" => {
+                let profile = field(row.profile, "profile")?;
+                problem(&mut dump, row.id)?.leeches.push(profile);
+            }
+            "suspended" => {
+                let susp = Suspension {
+                    profile: field(row.profile, "profile")?,
+                    until: row.until,
+                };
+                problem(&mut dump, row.id)?.suspended.push(susp);
+            }
+            "log" => {
+                dump.log.push(LogEntry {
+                                  stamp: field(row.stamp, "stamp")?,
+                                  score: field(row.score, "score")?,
+                                  probid: field(row.id, "id")?,
+                                  profile: field(row.profile, "profile")?,
+                              })
+            }
+            other => return Err(Error::Import(format!("unknown record {:?}", other))),
+        }
+    }
+    Ok(dump)
+}
+
+// Find the problem that a `learning`, `imported`, `leech` or `suspended` record belongs to, which
+// must already have been read.
+fn problem(dump: &mut Dump, id: Option<i64>) -> Result<&mut ProblemEntry> {
+    let id = field(id, "id")?;
+    dump.problems
+        .iter_mut()
+        .rev()
+        .find(|prob| prob.id == id)
+        .ok_or_else(|| Error::Import(format!("no problem {} before its state", id)))
+}
+
+// Extract a field that is required for a given kind of record.
+fn field<T>(value: Option<T>, name: &str) -> Result<T> {
+    value.ok_or_else(|| Error::Import(format!("missing {}", name)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+    use timelearn::{Clock, ManualClock, Steps};
+
+    // Exporting to CSV and importing again should give the same database.
+    #[test]
+    fn csv() {
+        let tmp_dir = TempDir::new("dump").unwrap();
+        let db_path = tmp_dir.path().join("learn.db");
+        let db_path = db_path.to_str().unwrap();
+        let csv_path = tmp_dir.path().join("learn.csv");
+        let csv_path = csv_path.to_str().unwrap();
+        let copy_path = tmp_dir.path().join("copy.db");
+        let copy_path = copy_path.to_str().unwrap();
+
+        let clock = ManualClock::new(1.5e9);
+        let mut st = Store::create(db_path, "simple").unwrap();
+        st.set_clock(Box::new(clock.clone()));
+        st.set_steps(Steps {
+                learning: vec![60.0, 600.0],
+                relearning: vec![],
+            })
+            .unwrap();
+        {
+            let mut p = st.populate().unwrap();
+            p.add_problem("plain", "answer").unwrap();
+            p.add_problem("comma, \"quote\"", "two\nlines").unwrap();
+            p.add_problem("empty", "").unwrap();
+            p.add_learning_problem("learned", "answer", clock.now() - 10.0, 1.0e5).unwrap();
+            p.add_tags("plain", &["a", "b"]).unwrap();
+            p.add_tags("empty", &["b"]).unwrap();
+            p.commit().unwrap();
+        }
+        st.add_profile("other").unwrap();
+
+        for _ in 0..2 {
+            let prob = st.get_nexts(1).unwrap().pop().unwrap();
+            st.update(prob, 3).unwrap();
+            clock.advance(0.5);
+        }
+        let prob = st.get_new().unwrap().unwrap();
+        st.suspend(&prob).unwrap();
+        let prob = st.get_new().unwrap().unwrap();
+        st.bury(&prob).unwrap();
+        st.set_profile("other").unwrap();
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        st.update(prob, 1).unwrap();
+
+        let dump = st.export().unwrap();
+        drop(st);
+        assert_eq!(dump.profiles.len(), 2);
+        assert_eq!(dump.log.len(), 3);
+        assert!(dump.problems.iter().any(|p| p.learning.iter().any(|l| l.phase == "learning")));
+        assert!(dump.problems.iter().any(|p| !p.imported.is_empty()));
+        assert!(dump.problems.iter().any(|p| p.suspended.iter().any(|s| s.until.is_none())));
+        assert!(dump.problems.iter().any(|p| p.suspended.iter().any(|s| s.until.is_some())));
+
+        export(db_path, csv_path).unwrap();
+        import(csv_path, copy_path).unwrap();
+        assert_eq!(Store::open(copy_path).unwrap().export().unwrap(), dump);
+    }
+}
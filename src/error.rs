@@ -0,0 +1,125 @@
+//! Errors from stenome.
+
+use csv;
+use rusqlite;
+use serde_json;
+use std::error;
+use std::fmt;
+use std::io;
+use timelearn;
+use zip::result::ZipError;
+
+/// The errors returned by stenome.
+#[derive(Debug)]
+pub enum Error {
+    /// An error from the learning database, or from interacting with the user.
+    Learn(timelearn::Error),
+    /// Text that could not be parsed as steno strokes.
+    Stroke(String),
+    /// The database holds a kind of problem that we don't know how to ask.
+    UnknownKind(String),
+    /// The program was not built with support for MIDI.
+    NoMidi,
+    /// The command line could not be understood.
+    Usage(String),
+    /// A JSON file could not be decoded.
+    Json(serde_json::Error),
+    /// A CSV file could not be read or written.
+    Csv(csv::Error),
+    /// An exported database could not be imported.
+    Import(String),
+    /// An Anki package could not be used.
+    Anki(String),
+    /// A zip file could not be read or written.
+    Zip(ZipError),
+    /// An error from a database other than the learning database.
+    Sql(rusqlite::Error),
+    /// An I/O error.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Learn(ref err) => err.fmt(f),
+            Error::Stroke(ref msg) => write!(f, "Invalid stroke: {}", msg),
+            Error::UnknownKind(ref kind) => write!(f, "Unknown store type {:?}", kind),
+            Error::NoMidi => write!(f, "Program not built with midi support"),
+            Error::Usage(ref msg) => write!(f, "Usage: {}", msg),
+            Error::Json(ref err) => write!(f, "Invalid JSON: {}", err),
+            Error::Csv(ref err) => write!(f, "CSV error: {}", err),
+            Error::Import(ref msg) => write!(f, "Invalid import: {}", msg),
+            Error::Anki(ref msg) => write!(f, "Anki package: {}", msg),
+            Error::Zip(ref err) => write!(f, "Zip error: {}", err),
+            Error::Sql(ref err) => write!(f, "Database error: {}", err),
+            Error::Io(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Learn(_) => "learning error",
+            Error::Stroke(_) => "invalid stroke",
+            Error::UnknownKind(_) => "unknown store type",
+            Error::NoMidi => "no midi support",
+            Error::Usage(_) => "usage error",
+            Error::Json(_) => "invalid JSON",
+            Error::Csv(_) => "CSV error",
+            Error::Import(_) => "invalid import",
+            Error::Anki(_) => "invalid Anki package",
+            Error::Zip(_) => "zip error",
+            Error::Sql(_) => "database error",
+            Error::Io(_) => "I/O error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Learn(ref err) => Some(err),
+            Error::Json(ref err) => Some(err),
+            Error::Csv(ref err) => Some(err),
+            Error::Zip(ref err) => Some(err),
+            Error::Sql(ref err) => Some(err),
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<timelearn::Error> for Error {
+    fn from(err: timelearn::Error) -> Error {
+        Error::Learn(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(err: ZipError) -> Error {
+        Error::Zip(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Sql(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
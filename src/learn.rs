@@ -1,8 +1,9 @@
 // Learning.
 
-use timelearn::{Problem, Store};
+use timelearn::{Phase, Problem, Stage, Store};
 use humanize_time;
 
+use Result;
 use Status;
 use User;
 
@@ -19,41 +20,55 @@ impl<'u> Learn<'u> {
         }
     }
 
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> Result<()> {
         loop {
             // TODO: Check for things that have expired we need to learn.
 
-            let words = self.store.get_nexts(2).unwrap();
-            let mut words = words.into_iter();
-            let mut word = match words.next() {
+            let mut words = self.store.get_nexts(2)?.into_iter();
+            let word = match words.next() {
                 None => {
-                    println!("No more words to learn\r");
-                    return;
+                    // Tell the user which limit, if any, ended the session.
+                    let budget = self.store.get_budget()?;
+                    if budget.reviews_exhausted() {
+                        println!("Review limit for this session reached\r");
+                    } else if budget.new_exhausted() && self.store.get_counts()?.unlearned > 0 {
+                        println!("No more words to learn today\r");
+                    } else {
+                        println!("No more words to learn\r");
+                    }
+                    return Ok(());
                 }
                 Some(word) => word,
             };
             let next = words.next();
 
-            let status = self.single(&mut word, next.as_ref());
+            let status = self.single(&word, next.as_ref())?;
 
             match status {
                 Status::Stopped => break,
-                Status::Continue(factor) => self.store.update(word, factor).unwrap(),
+                Status::Continue(factor) => self.store.update(word, factor)?,
+                Status::Suspend => self.store.suspend(&word)?,
             }
         }
+        Ok(())
     }
 
     // Learn a single word, updating its timing information based on how well it was learned.
-    fn single(&mut self, word: &Problem, next: Option<&Problem>) -> Status {
-        let counts = self.store.get_counts().unwrap();
+    fn single(&mut self, word: &Problem, next: Option<&Problem>) -> Result<Status> {
+        let counts = self.store.get_counts()?;
 
+        let phase = match word.get_phase() {
+            Phase::New | Phase::Review => String::new(),
+            Phase::Learning(step) => format!(" (learning step {})", step + 1),
+            Phase::Relearning(step) => format!(" (relearning step {})", step + 1),
+        };
         writeln!(self.user,
-                 "\r\nActive: {}, Later: {}, Unlearned: {}, Interval {}\r",
+                 "\r\nActive: {}, Later: {}, Unlearned: {}, Interval {}{}\r",
                  counts.active,
                  counts.later,
                  counts.unlearned,
-                 humanize_time(word.get_interval()))
-                .unwrap();
+                 humanize_time(word.get_interval()),
+                 phase)?;
         let mut active = 0;
         let mut learned = 0;
         for b in &counts.buckets {
@@ -61,26 +76,40 @@ impl<'u> Learn<'u> {
                      "  {:-4}: {:4} {}\r",
                      b.name,
                      b.count,
-                     stars(65, b.count, counts.active + counts.later))
-                    .unwrap();
-            // TODO: This shouldn't be done by string matching, it is fragile.
-            if b.name == "day" || b.name == "mon" {
-                learned += b.count;
-            } else {
-                active += b.count;
+                     stars(65, b.count, counts.active + counts.later))?;
+            match b.stage {
+                Stage::Active => active += b.count,
+                Stage::Learned => learned += b.count,
             }
         }
-        writeln!(self.user, "  active : {}\r", active).unwrap();
-        writeln!(self.user, "  learned: {}\r\n\r", learned).unwrap();
-        self.user.flush().unwrap();
+        writeln!(self.user, "  active : {}\r", active)?;
+        writeln!(self.user, "  learned: {}\r", learned)?;
+        writeln!(self.user,
+                 "  due today: {}, tomorrow: {}\r",
+                 counts.due_today,
+                 counts.due_tomorrow)?;
+        if counts.suspended > 0 || counts.buried > 0 {
+            writeln!(self.user,
+                     "  suspended: {}, buried: {}\r",
+                     counts.suspended,
+                     counts.buried)?;
+        }
+        if let Some(left) = counts.budget.new_left {
+            writeln!(self.user, "  new left today: {}\r", left)?;
+        }
+        if let Some(left) = counts.budget.reviews_left {
+            writeln!(self.user, "  reviews left  : {}\r", left)?;
+        }
+        writeln!(self.user, "\r")?;
+        self.user.flush()?;
 
-        self.user.single(word, next).unwrap()
+        Ok(self.user.single(word, next)?)
     }
 }
 
 // Print a line of stars resembling a histogram bar.  `len` is the number of stars to use, a is the
 // number in question, and total is the expected total.
-fn stars(len: usize, value: usize, total: usize) -> String {
+pub fn stars(len: usize, value: usize, total: usize) -> String {
     let mut buf = String::new();
     buf.push('|');
     let thresh = value as f64 / total as f64 * len as f64;
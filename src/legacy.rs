@@ -1,4 +1,4 @@
-/// Import the legacy json data into the new timelearn database.
+//! Import the legacy json data into the new timelearn database.
 
 use Result;
 use serde_json;
@@ -51,17 +51,24 @@ impl Words {
 
         // Start with the words being learned. The order will be based on the current intervals, so
         // not exactly matching the initial order, but this should at least get these before the
-        // unlearned words.
+        // unlearned words.  The learned words don't record their lesson, so they are tagged with
+        // the tags of the lesson they would be in.
         for w in &self.learning {
             let strokes = Stroke::slashed_str(&w.strokes);
             pop.add_learning_problem(&w.english, &strokes, w.next, w.interval)?;
+            if let Some(les) = self.lesson_of(&w.strokes) {
+                let tags: Vec<&str> = les.info.tags.split_whitespace().collect();
+                pop.add_tags(&w.english, &tags)?;
+            }
         }
 
-        // Then add all of the unlearned words.
+        // Then add all of the unlearned words, tagged with the tags of their lesson.
         for les in &self.unlearned {
+            let tags: Vec<&str> = les.info.tags.split_whitespace().collect();
             for w in &les.words {
                 let strokes = Stroke::slashed_str(&w.0);
                 pop.add_problem(&w.1, &strokes)?;
+                pop.add_tags(&w.1, &tags)?;
             }
         }
 
@@ -69,4 +76,51 @@ impl Words {
 
         Ok(())
     }
+
+    // The first lesson that a word with the given strokes fits in: one that includes every key the
+    // word uses, with a stroke that uses all of the keys the lesson requires.
+    fn lesson_of(&self, strokes: &[Stroke]) -> Option<&Lesson> {
+        self.unlearned.iter().find(|les| {
+            let Stroke(include) = les.info.include;
+            let Stroke(require) = les.info.require;
+            strokes.iter().all(|&Stroke(s)| s & !include == 0) &&
+            strokes.iter().any(|&Stroke(s)| s & require == require)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lesson(include: &str, require: &str, tags: &str) -> Lesson {
+        Lesson {
+            info: LessonInfo {
+                title: require.to_owned(),
+                include: Stroke::parse_stroke(include).unwrap(),
+                require: Stroke::parse_stroke(require).unwrap(),
+                tags: tags.to_owned(),
+            },
+            words: vec![],
+        }
+    }
+
+    // Learned words should be found in the first lesson that they fit in.
+    #[test]
+    fn lesson_of() {
+        let words = Words {
+            unlearned: vec![lesson("HAO*EU", "H", "h"),
+                            lesson("HRAO*EU", "R", "r"),
+                            lesson("HRAO*EUF", "-F", "f")],
+            learning: vec![],
+        };
+        let tags = |text: &str| {
+            words.lesson_of(&Stroke::parse_strokes(text).unwrap()).map(|les| les.info.tags.as_str())
+        };
+        assert_eq!(tags("HA"), Some("h"));
+        assert_eq!(tags("HRA"), Some("r"));
+        assert_eq!(tags("HAF/RA"), Some("f"));
+        assert_eq!(tags("HA/RA"), Some("r"));
+        assert_eq!(tags("TA"), None);
+    }
 }
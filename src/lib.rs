@@ -1,34 +1,43 @@
 // Learn Steno using SRA.
 
+extern crate csv;
 extern crate rand;
 extern crate rusqlite;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+extern crate sha1;
+extern crate tempdir;
 extern crate termion;
 
 extern crate timelearn;
+extern crate zip;
 
 #[cfg(feature = "midi")]
 extern crate midilearn;
 
-#[cfg(test)]
-extern crate tempdir;
-
-use std::error;
+use std::fs::File;
+use std::io::{stdin, stdout, Write};
 use std::result;
 
+pub use anki::{export_anki, import_anki};
+pub use dump::{export, import};
+pub use error::Error;
 pub use stroke::Stroke;
 // pub use words::{Counts, LearnWord, Words, Store};
 use timelearn::Store;
-pub use timelearn::{Problem, Status, User};
+pub use timelearn::{Granularity, Missing, Problem, Status, User};
 use learn::Learn;
 use steno::Steno;
 use simple::Simple;
 
-pub type Result<T> = result::Result<T, Box<error::Error + Send + Sync>>;
+pub type Result<T> = result::Result<T, Error>;
 
+mod anki;
+mod dump;
+mod error;
 mod stroke;
 mod learn;
 mod simple;
@@ -37,62 +46,347 @@ pub mod legacy;
 
 pub use midi::record;
 
-pub fn run(path: &str) {
-    let st = Store::open(path).unwrap();
+/// Run a learning session on the database at `path`.  If `tag` is given, only problems with that
+/// tag will be asked.  The session uses the given profile, or the default one.
+pub fn run(path: &str, tag: Option<&str>, profile: Option<&str>) -> Result<()> {
+    let mut st = open_for_writing(path)?;
+    st.set_tag_filter(tag);
+    if let Some(profile) = profile {
+        st.set_profile(profile)?;
+    }
 
     if st.get_kind() == "steno" {
-        run_steno(st);
+        run_steno(st)
     } else if st.get_kind() == "simple" {
-        run_simple(st);
+        run_simple(st)
     } else if st.get_kind() == "midi" {
-        midi::run_midi(st);
+        midi::run_midi(st)
     } else {
-        panic!("Unknown store type '{}'", st.get_kind());
+        Err(Error::UnknownKind(st.get_kind().to_owned()))
+    }
+}
+
+/// Print the profiles in the database at `path`, after first adding the profile `add`, if given.
+pub fn profiles(path: &str, add: Option<&str>) -> Result<()> {
+    let mut st = Store::open(path)?;
+    if let Some(name) = add {
+        st.backup()?;
+        st.add_profile(name)?;
+    }
+    for name in st.get_profiles()? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Merge the answers from the database at `other`, another copy of the same problems, into the
+/// database at `path`.  The other database is only read, so it must already use the current schema.
+pub fn merge(path: &str, other: &str) -> Result<()> {
+    let mut st = open_for_writing(path)?;
+    let dump = Store::open_read_only(other)?.export()?;
+    let report = st.merge(&dump)?;
+    println!("Added {} problems and {} reviews, skipped {} reviews already present",
+             report.problems_added,
+             report.reviews_added,
+             report.duplicates);
+    println!("Recomputed the learning state of {} problems", report.replayed);
+    Ok(())
+}
+
+/// Replay the log of the database at `path` through its scheduler, for each profile, and print the
+/// problems whose learning state differs from the stored state.  If `write` is set, the learning
+/// state is replaced.
+pub fn rebuild(path: &str, write: bool) -> Result<()> {
+    let mut st = Store::open(path)?;
+    if write {
+        st.backup()?;
+    }
+    for profile in st.get_profiles()? {
+        st.set_profile(&profile)?;
+        let rebuilt = st.rebuild(write)?;
+        let mut changed = 0;
+        for reb in rebuilt.iter().filter(|reb| reb.changed()) {
+            changed += 1;
+            let (stored, shift) = match reb.stored {
+                Some((next, interval)) => {
+                    let shift = reb.replayed.0 - next;
+                    let sign = if shift < 0.0 { "-" } else { "+" };
+                    (humanize_time(interval), format!("{}{}", sign, humanize_time(shift.abs())))
+                }
+                None => ("-".to_owned(), "-".to_owned()),
+            };
+            println!("{:>14} -> {:>14}  due {:>15}  {}",
+                     stored,
+                     humanize_time(reb.replayed.1),
+                     shift,
+                     reb.question);
+        }
+        println!("{}: {} of {} problems differ{}",
+                 profile,
+                 changed,
+                 rebuilt.len(),
+                 if write { ", rebuilt" } else { "" });
+    }
+    Ok(())
+}
+
+/// Fit the parameters of the scheduler of the database at `path` to its log, and print them.  The
+/// parameters are only written to the database if the user confirms.
+pub fn optimize(path: &str) -> Result<()> {
+    let mut st = Store::open(path)?;
+    let fit = st.optimize()?;
+    if fit.reviews == 0 {
+        println!("There are no reviews to fit the {} scheduler to", fit.name);
+        return Ok(());
+    }
+
+    println!("Fitted the {} scheduler to {} reviews", fit.name, fit.reviews);
+    for (i, (old, new)) in fit.old_params.iter().zip(&fit.params).enumerate() {
+        println!("  {:2}: {:10.4} -> {:10.4}", i, old, new);
+    }
+    println!("Log loss per review: {:.4} -> {:.4}", fit.old_loss, fit.loss);
+    println!("Expected workload: {:+.1}%", (fit.workload - 1.0) * 100.0);
+    if fit.params == fit.old_params {
+        return Ok(());
+    }
+
+    print!("Write the fitted parameters to the database? [y/N] ");
+    stdout().flush()?;
+    let mut line = String::new();
+    stdin().read_line(&mut line)?;
+    if line.trim().eq_ignore_ascii_case("y") {
+        st.backup()?;
+        st.set_scheduler(timelearn::scheduler::from_config(&fit.name, &fit.params)?)?;
+        println!("Written");
+    }
+    Ok(())
+}
+
+/// List the backups of the database at `path`, most recent first, or, if `which` is given, restore
+/// the backup with that number in the list.  The contents being replaced are backed up first.
+pub fn restore(path: &str, which: Option<usize>) -> Result<()> {
+    let mut backups = Store::backups(path)?;
+    backups.reverse();
+    match which {
+        None => {
+            if backups.is_empty() {
+                println!("There are no backups of {}", path);
+            }
+            for (i, backup) in backups.iter().enumerate() {
+                println!("{:3}  {}", i + 1, backup.display());
+            }
+        }
+        Some(num) => {
+            let backup = match num.checked_sub(1).and_then(|i| backups.get(i)) {
+                Some(backup) => backup,
+                None => return Err(Error::Usage(format!("no backup {} of {}", num, path))),
+            };
+            let saved = Store::restore(path, backup)?;
+            println!("Restored {} from {}, after saving it to {}",
+                     path,
+                     backup.display(),
+                     saved.display());
+        }
+    }
+    Ok(())
+}
+
+/// Open the database at `path` for a command that changes it, first backing it up.
+fn open_for_writing(path: &str) -> Result<Store> {
+    let st = Store::open(path)?;
+    st.backup()?;
+    Ok(st)
+}
+
+/// Open the database at `path` for commands that only read it.  It is opened read-only, so that it
+/// is left as it is, and can be read while another session has it open.  Only a database that
+/// uses an older schema is opened for writing, to upgrade it.
+fn open_for_reading(path: &str) -> Result<Store> {
+    match Store::open_read_only(path) {
+        Err(timelearn::Error::SchemaMismatch(_)) => Ok(Store::open(path)?),
+        result => Ok(result?),
+    }
+}
+
+/// Print the problems in the database at `path` that have been detected as leeches.
+pub fn leeches(path: &str) -> Result<()> {
+    let st = open_for_reading(path)?;
+    let leeches = st.get_leeches()?;
+    if leeches.is_empty() {
+        println!("No leeches");
+        return Ok(());
+    }
+    for leech in &leeches {
+        println!("{:3} {}{:<20} {}",
+                 leech.lapses,
+                 if leech.suspended { "*" } else { " " },
+                 leech.question,
+                 leech.answer);
     }
+    println!("{} leeches ({} lapses or more, * = suspended)",
+             leeches.len(),
+             st.get_leech_policy().threshold);
+    Ok(())
 }
 
-fn run_steno(st: Store) {
-    let mut user = Steno::new().unwrap();
+/// Print statistics about the learning of the problems in the database at `path`, computed from
+/// the log.  The work done is shown for the last `days` days that had any.
+pub fn stats(path: &str, days: usize) -> Result<()> {
+    let st = open_for_reading(path)?;
+    let stats = st.get_stats()?;
+
+    match stats.average_grade {
+        None => println!("{} reviews", stats.reviews),
+        Some(grade) => println!("{} reviews, average grade {:.2}", stats.reviews, grade),
+    }
+
+    println!("Retention by interval:");
+    for ret in &stats.retention {
+        match ret.rate() {
+            None => println!("  {:-4}: {:6}", ret.name, ret.reviews),
+            Some(rate) => println!("  {:-4}: {:6} {:5.1}%", ret.name, ret.reviews, rate * 100.0),
+        }
+    }
+
+    println!("Recent days:");
+    let cur = st.now();
+    let skip = stats.days.len().saturating_sub(days);
+    for day in &stats.days[skip..] {
+        println!("  {:4} days ago: {:5} reviews, {:4} new",
+                 ((cur - day.day) / 86400.0) as i64,
+                 day.reviews,
+                 day.new);
+    }
+
+    let mat = &stats.maturity;
+    match (mat.mean_time, mat.median_time) {
+        (Some(mean), Some(median)) => {
+            println!("{} matured, mean time {}, median {}",
+                     mat.matured,
+                     humanize_time(mean),
+                     humanize_time(median))
+        }
+        _ => println!("None matured"),
+    }
+
+    let failed: usize = stats.fails.iter().map(|f| f.fails).sum();
+    println!("{} fails over {} problems", failed, stats.fails.len());
+    for fail in stats.fails.iter().take(10) {
+        println!("  {:3} {}", fail.fails, fail.question);
+    }
+    Ok(())
+}
+
+/// Print a chart of the number of problems in the database at `path` that will come due in each
+/// of the next `count` hours or days.
+pub fn forecast(path: &str, granularity: Granularity, count: usize) -> Result<()> {
+    let st = open_for_reading(path)?;
+    let fc = st.get_forecast(granularity, count)?;
+    let most = fc.periods.iter().map(|p| p.count).max().unwrap_or(0);
+
+    println!("Overdue: {}", fc.overdue);
+    for (i, period) in fc.periods.iter().enumerate() {
+        let label = match (granularity, i) {
+            (Granularity::Days, 0) => "today".to_owned(),
+            (Granularity::Days, 1) => "tomorrow".to_owned(),
+            (Granularity::Days, _) => format!("+{} days", i),
+            (Granularity::Hours, 0) => "this hour".to_owned(),
+            (Granularity::Hours, _) => format!("+{} hours", i),
+        };
+        println!("  {:>10}: {:5} {}",
+                 label,
+                 period.count,
+                 learn::stars(50, period.count, most));
+    }
+    Ok(())
+}
+
+/// Return the problem with the given question to the database at `path`, after it has been
+/// suspended or buried.
+pub fn unsuspend(path: &str, question: &str) -> Result<()> {
+    let mut st = open_for_writing(path)?;
+    st.unsuspend(question)?;
+    Ok(())
+}
+
+/// A single problem of a problem list given to `sync`.
+#[derive(Debug, Deserialize)]
+struct ListProblem {
+    question: String,
+    answer: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Bring the problems in the database at `path` up to date with the JSON problem list in the file
+/// `list`, keeping the learning progress of problems that are still in the list.  `missing` says
+/// what to do with problems that are no longer in the list.
+pub fn sync(path: &str, list: &str, missing: Missing) -> Result<()> {
+    let probs: Vec<ListProblem> = serde_json::from_reader(File::open(list)?)?;
+
+    let mut st = open_for_writing(path)?;
+    let mut sync = st.sync()?;
+    for prob in &probs {
+        sync.add_problem(&prob.question, &prob.answer)?;
+        let tags: Vec<&str> = prob.tags.iter().map(String::as_str).collect();
+        sync.add_tags(&prob.question, &tags)?;
+    }
+    let report = sync.finish(missing)?;
+    println!("{} added, {} updated, {} unchanged, {} restored, {} retired, {} deleted",
+             report.added,
+             report.updated,
+             report.unchanged,
+             report.restored,
+             report.retired,
+             report.deleted);
+    Ok(())
+}
+
+fn run_steno(st: Store) -> Result<()> {
+    let mut user = Steno::new()?;
     let mut learn = Learn::new(st, &mut user);
-    learn.run();
+    learn.run()
 }
 
-fn run_simple(st: Store) {
-    let mut user = Simple::new().unwrap();
+fn run_simple(st: Store) -> Result<()> {
+    let mut user = Simple::new()?;
     let mut learn = Learn::new(st, &mut user);
-    learn.run();
+    learn.run()
 }
 
 #[cfg(feature = "midi")]
 mod midi {
     use learn::Learn;
     use midilearn::MidiLearn;
-    use timelearn::Store;
+    use timelearn::{self, Store};
+    use Result;
 
-    pub fn run_midi(st: Store) {
+    pub fn run_midi(st: Store) -> Result<()> {
         MidiLearn::with_new(|user: &mut MidiLearn| {
             let mut learn = Learn::new(st, user);
-            learn.run();
-        }).unwrap();
+            learn.run()
+        }).map_err(timelearn::Error::User)?
     }
 
-    pub fn record() {
-        MidiLearn::with_new(|user: &mut MidiLearn| {
-            user.record_lick().unwrap();
-        }).unwrap();
+    pub fn record() -> Result<()> {
+        MidiLearn::with_new(|user: &mut MidiLearn| user.record_lick())
+            .and_then(|r| r)
+            .map_err(timelearn::Error::User)?;
+        Ok(())
     }
 }
 
 #[cfg(not(feature = "midi"))]
 mod midi {
     use timelearn::Store;
+    use {Error, Result};
 
-    pub fn run_midi(_st: Store) {
-        panic!("Program not built with midi support");
+    pub fn run_midi(_st: Store) -> Result<()> {
+        Err(Error::NoMidi)
     }
 
-    pub fn record() {
-        panic!("Program not built with midi support");
+    pub fn record() -> Result<()> {
+        Err(Error::NoMidi)
     }
 }
 
@@ -2,8 +2,9 @@ extern crate termion;
 extern crate stenome;
 
 use std::env;
+use std::process;
 
-use stenome::Result;
+use stenome::{Error, Granularity, Missing, Result};
 use stenome::legacy::Words;
 
 // Stenome expects Plover to do the decoding of the steno keyboard.  To make this work, you should
@@ -13,22 +14,105 @@ use stenome::legacy::Words;
 // to decode the raw steno strokes as they are sent.
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("stenome: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     let args: Vec<_> = env::args().skip(1).collect();
     if args == &["create"] {
-        create().unwrap();
-    } else if args.len() == 2 && args[0] == "run" {
-        stenome::run(&args[1]);
+        create()
+    } else if args.len() >= 2 && args[0] == "run" {
+        let (tag, profile) = run_options(&args[2..])?;
+        stenome::run(&args[1], tag, profile)
+    } else if args.len() == 2 && args[0] == "profiles" {
+        stenome::profiles(&args[1], None)
+    } else if args.len() == 4 && args[0] == "profiles" && args[2] == "--add" {
+        stenome::profiles(&args[1], Some(&args[3]))
+    } else if args.len() == 3 && args[0] == "merge" {
+        stenome::merge(&args[1], &args[2])
+    } else if args.len() == 2 && args[0] == "rebuild" {
+        stenome::rebuild(&args[1], false)
+    } else if args.len() == 3 && args[0] == "rebuild" && args[2] == "--write" {
+        stenome::rebuild(&args[1], true)
+    } else if args.len() == 2 && args[0] == "optimize" {
+        stenome::optimize(&args[1])
+    } else if args.len() == 2 && args[0] == "leeches" {
+        stenome::leeches(&args[1])
+    } else if args.len() == 2 && args[0] == "restore" {
+        stenome::restore(&args[1], None)
+    } else if args.len() == 3 && args[0] == "restore" {
+        stenome::restore(&args[1], Some(parse_count(&args[2])?))
+    } else if args.len() == 3 && args[0] == "unsuspend" {
+        stenome::unsuspend(&args[1], &args[2])
+    } else if args.len() == 3 && args[0] == "sync" {
+        stenome::sync(&args[1], &args[2], Missing::Keep)
+    } else if args.len() == 4 && args[0] == "sync" && args[3] == "--retire" {
+        stenome::sync(&args[1], &args[2], Missing::Retire)
+    } else if args.len() == 4 && args[0] == "sync" && args[3] == "--delete" {
+        stenome::sync(&args[1], &args[2], Missing::Delete)
+    } else if args.len() == 3 && args[0] == "export" {
+        stenome::export(&args[1], &args[2])
+    } else if args.len() == 3 && args[0] == "import" {
+        stenome::import(&args[1], &args[2])
+    } else if args.len() == 3 && args[0] == "import-anki" {
+        stenome::import_anki(&args[1], &args[2], false)
+    } else if args.len() == 4 && args[0] == "import-anki" && args[3] == "--history" {
+        stenome::import_anki(&args[1], &args[2], true)
+    } else if args.len() == 3 && args[0] == "export-anki" {
+        stenome::export_anki(&args[1], &args[2])
+    } else if args.len() == 2 && args[0] == "stats" {
+        stenome::stats(&args[1], 14)
+    } else if args.len() == 2 && args[0] == "forecast" {
+        stenome::forecast(&args[1], Granularity::Days, 14)
+    } else if args.len() == 4 && args[0] == "forecast" && args[2] == "--days" {
+        stenome::forecast(&args[1], Granularity::Days, parse_count(&args[3])?)
+    } else if args.len() == 4 && args[0] == "forecast" && args[2] == "--hours" {
+        stenome::forecast(&args[1], Granularity::Hours, parse_count(&args[3])?)
     } else if args.len() == 1 && args[0] == "record" {
-        stenome::record();
+        stenome::record()
     } else {
-        println!("Usage: {{create|run db}}");
+        println!("Usage: {{create|run db [--tag tag] [--profile name]|\
+                  profiles db [--add name]|merge db other.db|rebuild db [--write]|optimize db|\
+                  restore db [n]|leeches db|unsuspend db question|\
+                  sync db list.json [--retire|--delete]|\
+                  export db file.{{json|csv}}|import file.{{json|csv}} db|\
+                  import-anki file.apkg db [--history]|export-anki db file.apkg|\
+                  stats db|forecast db [--days n|--hours n]}}");
+        Ok(())
+    }
+}
+
+/// Parse the options of the run command, each of which is followed by a value.  Returns the tag
+/// and the profile.
+fn run_options(args: &[String]) -> Result<(Option<&str>, Option<&str>)> {
+    let mut tag = None;
+    let mut profile = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match args.next() {
+            Some(value) => value.as_str(),
+            None => return Err(Error::Usage(format!("{} needs a value", arg))),
+        };
+        match arg.as_str() {
+            "--tag" => tag = Some(value),
+            "--profile" => profile = Some(value),
+            _ => return Err(Error::Usage(format!("unknown option {:?}", arg))),
+        }
     }
-    // stenome::run();
+    Ok((tag, profile))
+}
+
+/// Parse a count given on the command line.
+fn parse_count(text: &str) -> Result<usize> {
+    text.parse().map_err(|_| Error::Usage(format!("invalid count {:?}", text)))
 }
 
 /// Create a new database, by loading data from an existing json file.
 fn create() -> Result<()> {
-    let words = Words::load("learning.json").unwrap();
-    words.create_db("words.db").unwrap();
+    let words = Words::load("learning.json")?;
+    words.create_db("words.db")?;
     Ok(())
 }
@@ -3,12 +3,12 @@
 //! Implements a simple type of Q/A.  The question and answer are both text.  It presents the
 //! question, waits for the user to press a key, shows the answer, and asks the user how well they
 //! did.  Useful when the "answer" is a performance type of action, such as saying something out
-//! loud, or playing something on a musical instrument.
+//! loud, or playing something on a musical instrument.  Pressing '@' at either prompt suspends the
+//! problem.
 
-use Result;
 use Status;
 use User;
-use timelearn::Problem;
+use timelearn::{Error, Problem, Result};
 
 use std::io::{self, stdin, Stdin, stdout, Stdout, Write};
 use termion::event::Key;
@@ -27,6 +27,12 @@ impl Simple {
             stdout: stdout().into_raw_mode()?,
         })
     }
+
+    // Report that the problem is being suspended.
+    fn suspended(&mut self) -> Result<Status> {
+        write!(self, "\r\n(Suspended)\r\n\n\n")?;
+        Ok(Status::Suspend)
+    }
 }
 
 impl User for Simple {
@@ -38,29 +44,31 @@ impl User for Simple {
         if word.answer != "play" {
             loop {
                 let c = match self.keys.next() {
-                    None => panic!("End of input"),
+                    None => return Err(Error::EndOfInput),
                     Some(c) => c,
                 };
                 match c? {
                     Key::Esc => return Ok(Status::Stopped),
+                    Key::Char('@') => return self.suspended(),
                     Key::Char(' ') => break,
                     _ => {}
                 }
             }
-            write!(self, "\r\n\nA: {} (1, bad, 4 - good): ", word.answer)?;
+            write!(self, "\r\n\nA: {} (1, bad, 4 - good, @ - suspend): ", word.answer)?;
         } else {
-            write!(self, "\r\n\n    (1 - bad, 4 - good): ")?;
+            write!(self, "\r\n\n    (1 - bad, 4 - good, @ - suspend): ")?;
         }
         self.flush()?;
 
         // Wait for the 1-4 answer or escape.
         loop {
             let c = match self.keys.next() {
-                None => panic!("End of input"),
+                None => return Err(Error::EndOfInput),
                 Some(c) => c,
             };
             match c? {
                 Key::Esc => return Ok(Status::Stopped),
+                Key::Char('@') => return self.suspended(),
                 Key::Char(x) if x >= '1' && x <= '4' => {
                     let ch = (x as u8) - ('1' as u8) + 1;
                     write!(self, "\r\n(Learned: {})\r\n", ch)?;
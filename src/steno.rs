@@ -1,6 +1,5 @@
 //! Manage learning from a Steno device.
 
-use Result;
 use stroke::Stroke;
 use Status;
 use humanize_time;
@@ -10,7 +9,7 @@ use termion::event::Key;
 use termion::input::{Keys, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::color;
-use timelearn::Problem;
+use timelearn::{Error, Problem, Result};
 use User;
 
 pub struct Steno {
@@ -30,18 +29,19 @@ impl Steno {
            })
     }
 
-    /// Attempt to read a stroke from the terminal, or returns None if there are no strokes
-    /// available.
-    pub fn read_stroke(&mut self) -> Result<Option<Stroke>> {
+    /// Attempt to read a stroke from the terminal.  Escape stops learning, and '@' (which never
+    /// appears in a raw stroke) suspends the current problem.
+    pub fn read_stroke(&mut self) -> Result<Input> {
         let mut chars = String::new();
 
         loop {
             let c = match self.keys.next() {
-                None => panic!("End of input"),
+                None => return Err(Error::EndOfInput),
                 Some(c) => c,
             };
             match c? {
-                Key::Esc => return Ok(None),
+                Key::Esc => return Ok(Input::Stop),
+                Key::Char('@') => return Ok(Input::Suspend),
                 Key::Char(' ') => {
                     if !chars.is_empty() {
                         match Stroke::parse_stroke(&chars) {
@@ -51,7 +51,7 @@ impl Steno {
                                     self.counts.remove(1);
                                 }
                                 chars.clear();
-                                return Ok(Some(st));
+                                return Ok(Input::Stroke(st));
                             }
                             Err(e) => {
                                 writeln!(self, "Invalid stroke received: {:?}\r", e)?;
@@ -70,7 +70,7 @@ impl Steno {
                         }
                         Some(1) => {
                             // Fully backspaced, return the backspace.
-                            return Ok(Some(Stroke::make_star()));
+                            return Ok(Input::Stroke(Stroke::make_star()));
                         }
                         Some(count) => self.counts.push(count - 1),
                     }
@@ -84,10 +84,20 @@ impl Steno {
     }
 }
 
+/// The input read from the steno device.
+pub enum Input {
+    /// A single stroke.
+    Stroke(Stroke),
+    /// The user wants to stop learning.
+    Stop,
+    /// The user wants to suspend the current problem.
+    Suspend,
+}
+
 impl User for Steno {
     /// Ask the user to stroke a single problem, returning `Status` indicating how the user did.
     fn single(&mut self, word: &Problem, _next: Option<&Problem>) -> Result<Status> {
-        let mut state = Single::new(self, word);
+        let mut state = Single::new(self, word)?;
         state.run()
     }
 }
@@ -112,14 +122,16 @@ struct Single<'t, 'w> {
 }
 
 impl<'t, 'w> Single<'t, 'w> {
-    fn new<'tt, 'ww>(user: &'tt mut Steno, word: &'ww Problem) -> Single<'tt, 'ww> {
-        Single {
-            user: user,
-            word: word,
-            strokes: Stroke::parse_strokes(&word.answer).unwrap(),
-            input: vec![],
-            errors: 0,
-        }
+    fn new<'tt, 'ww>(user: &'tt mut Steno, word: &'ww Problem) -> Result<Single<'tt, 'ww>> {
+        let strokes = Stroke::parse_strokes(&word.answer)
+            .map_err(|e| Error::CorruptAnswer(format!("{:?}: {}", word.answer, e)))?;
+        Ok(Single {
+               user: user,
+               word: word,
+               strokes: strokes,
+               input: vec![],
+               errors: 0,
+           })
     }
 
     fn prompt(&mut self) -> Result<()> {
@@ -149,11 +161,15 @@ impl<'t, 'w> Single<'t, 'w> {
             }
 
             let stroke = match self.user.read_stroke()? {
-                None => {
+                Input::Stop => {
                     result = Status::Stopped;
                     break;
                 }
-                Some(st) => st,
+                Input::Suspend => {
+                    result = Status::Suspend;
+                    break;
+                }
+                Input::Stroke(st) => st,
             };
             if stroke.is_star() {
                 self.input.pop();
@@ -173,6 +189,7 @@ impl<'t, 'w> Single<'t, 'w> {
                          humanize_time(self.word.get_interval()))?;
             }
             Status::Stopped => writeln!(self.user, "\r")?,
+            Status::Suspend => writeln!(self.user, "\r\nSuspended\r")?,
         }
         self.user.flush()?;
         Ok(result)
@@ -1,6 +1,6 @@
 //! A Steno Stroke represents a set of keys that are pressed together on the keyboard.
 
-use Result;
+use {Error, Result};
 use serde::{de, ser};
 use std::fmt;
 use std::io::Write;
@@ -23,7 +23,7 @@ impl Stroke {
         for ch in text.chars() {
             if ch == '/' {
                 if bits == 0 {
-                    return Err("Empty stroke".into());
+                    return Err(Error::Stroke("Empty stroke".to_owned()));
                 }
                 result.push(Stroke(bits));
                 full_iter = FULL_STENO.chars().enumerate();
@@ -36,7 +36,7 @@ impl Stroke {
                 // Technically, this should be at position 7, but one of the lessons includes a
                 // hyphen after a '*'.
                 if pos >= 10 {
-                    return Err(format!("Invalid '-' position in {:?}", text).into());
+                    return Err(Error::Stroke(format!("Invalid '-' position in {:?}", text)));
                 }
                 pos = 12;
                 loop {
@@ -59,13 +59,15 @@ impl Stroke {
                         }
                     }
                     None => {
-                        return Err(format!("Invalid char in text: {:?} ({:?})", text, ch).into())
+                        return Err(Error::Stroke(format!("Invalid char in text: {:?} ({:?})",
+                                                        text,
+                                                        ch)))
                     }
                 }
             }
         }
         if bits == 0 {
-            return Err("Empty stroke".into());
+            return Err(Error::Stroke("Empty stroke".to_owned()));
         }
         result.push(Stroke(bits));
 
@@ -77,12 +79,12 @@ impl Stroke {
         let mut itr = Self::parse_strokes(text)?.into_iter();
 
         let stroke = match itr.next() {
-            None => return Err("No strokes in string".into()),
+            None => return Err(Error::Stroke("No strokes in string".to_owned())),
             Some(stroke) => stroke,
         };
         match itr.next() {
             None => (),
-            Some(_) => return Err("Expecting only a single stroke".into()),
+            Some(_) => return Err(Error::Stroke("Expecting only a single stroke".to_owned())),
         }
         Ok(stroke)
     }
@@ -0,0 +1,140 @@
+//! Backups.
+//!
+//! `Store::backup` copies the database, using SQLite's online backup API, into a directory
+//! alongside it that is named with a `.backups` suffix.  Each copy is named after the database and
+//! the time (in UTC) it was made, followed by a counter that tells apart copies made within the
+//! same second, so the copies sort in the order they were made.  Once there are more than the
+//! configured number of them, the oldest are removed.  The number is kept in the `config` table.
+//! Restoring a backup first backs up the contents it replaces, so that a restore can be undone.
+
+use rusqlite::{Connection, DatabaseName};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use lock::SessionLock;
+use {get_config, set_config, Error, Result};
+
+/// The number of backups that are kept by databases that don't configure it.
+pub const DEFAULT_BACKUPS: usize = 10;
+
+static BACKUPS: &'static str = "backups";
+
+/// Read the number of backups to keep from the config table.
+pub fn get_count(conn: &Connection) -> Result<usize> {
+    match get_config(conn, BACKUPS)? {
+        None => Ok(DEFAULT_BACKUPS),
+        Some(text) => {
+            match text.parse() {
+                Ok(count) if count > 0 => Ok(count),
+                _ => Err(Error::InvalidConfig(format!("{}: {:?}", BACKUPS, text))),
+            }
+        }
+    }
+}
+
+/// Write the number of backups to keep to the config table.
+pub fn set_count(conn: &Connection, count: usize) -> Result<()> {
+    if count == 0 {
+        return Err(Error::InvalidConfig(format!("{}: at least one must be kept", BACKUPS)));
+    }
+    set_config(conn, BACKUPS, &count.to_string())
+}
+
+/// Copy the database open on `conn` into its backup directory, naming the copy with the time
+/// `cur`, and remove the oldest copies beyond the configured number.  Returns the name of the
+/// copy.
+pub fn backup(conn: &Connection, cur: f64) -> Result<PathBuf> {
+    let name = copy(conn, cur)?;
+    prune(&db_path(conn)?, get_count(conn)?)?;
+    Ok(name)
+}
+
+// Copy the database open on `conn` into its backup directory, without removing any old copies.
+fn copy(conn: &Connection, cur: f64) -> Result<PathBuf> {
+    let path = db_path(conn)?;
+    let path = path.as_path();
+    let stamp: String = conn.query_row("SELECT strftime('%Y%m%d-%H%M%S', ?, 'unixepoch')",
+                                       &[&cur],
+                                       |row| row.get(0))?;
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+
+    let mut seq = 0;
+    let name = loop {
+        let mut name = prefix(path);
+        name.push(format!("{}-{:03}.bak", stamp, seq));
+        let name = dir.join(name);
+        if !name.exists() {
+            break name;
+        }
+        seq += 1;
+    };
+    conn.backup(DatabaseName::Main, &name, None)?;
+    Ok(name)
+}
+
+// Remove the oldest backups of the database at `path`, keeping `count` of them.
+fn prune(path: &Path, count: usize) -> Result<()> {
+    let mut all = list(path)?;
+    while all.len() > count {
+        fs::remove_file(all.remove(0))?;
+    }
+    Ok(())
+}
+
+/// The backups of the database at `path`, oldest first.
+pub fn list(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = backup_dir(path);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let prefix = prefix(path);
+    let prefix = prefix.to_string_lossy();
+    let mut result = vec![];
+    for entry in fs::read_dir(&dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&*prefix) && name.ends_with(".bak") {
+            result.push(dir.join(&*name));
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// Replace the contents of the database at `path` with those of the backup at `backup`, after
+/// backing up the current contents, naming the copy with the time `cur`.  The old backups are only
+/// pruned once the restore is done, so that the one being restored is kept until then.  The
+/// session lock is taken while this is done.  Returns the name of the copy of the replaced
+/// contents.
+pub fn restore(path: &Path, backup: &Path, cur: f64) -> Result<PathBuf> {
+    let _lock = SessionLock::acquire(path)?;
+    let mut conn = Connection::open(path)?;
+    let count = get_count(&conn)?;
+    let saved = copy(&conn, cur)?;
+    conn.restore(DatabaseName::Main, backup, None)?;
+    prune(path, count)?;
+    Ok(saved)
+}
+
+// The file holding the database open on `conn`.
+fn db_path(conn: &Connection) -> Result<PathBuf> {
+    let path: String = conn.query_row("SELECT file FROM pragma_database_list WHERE name = 'main'",
+                                      &[],
+                                      |row| row.get(0))?;
+    Ok(PathBuf::from(path))
+}
+
+// The directory holding the backups of the database at `path`.
+fn backup_dir(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".backups");
+    PathBuf::from(name)
+}
+
+// The start of the names of the backups of the database at `path`.
+fn prefix(path: &Path) -> OsString {
+    let mut name = path.file_name().map(|name| name.to_owned()).unwrap_or_default();
+    name.push(".");
+    name
+}
@@ -0,0 +1,192 @@
+//! Histogram buckets of learning intervals.
+//!
+//! `Store::get_counts` groups the learned problems into buckets by their interval.  The buckets
+//! are described by a list of `BucketSpec`s, each holding the problems with an interval up to its
+//! limit, and above the limit of the bucket before.  The last bucket also holds any problems with
+//! longer intervals than its limit.  Each bucket is classified by a `Stage`, so that frontends can
+//! tell which problems are still being learned.
+//!
+//! The buckets are kept in the `config` table, as a comma separated list of `name:limit:stage`.
+
+use rusqlite::Connection;
+use {get_config, set_config, Error, Result, DAY};
+
+/// How far along the problems in a bucket are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// The problems are still being actively learned, and are asked frequently.
+    Active,
+    /// The problems have been learned, and are only asked occasionally.
+    Learned,
+}
+
+/// The description of a single bucket.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BucketSpec {
+    /// A short description of the bucket.
+    pub name: String,
+    /// The longest interval, in seconds, of problems in this bucket.
+    pub limit: f64,
+    /// How far along the problems in this bucket are.
+    pub stage: Stage,
+}
+
+impl BucketSpec {
+    /// Construct a bucket description.
+    pub fn new(name: &str, limit: f64, stage: Stage) -> BucketSpec {
+        BucketSpec {
+            name: name.to_owned(),
+            limit: limit,
+            stage: stage,
+        }
+    }
+}
+
+/// The buckets used by databases that don't configure them.
+pub fn default_buckets() -> Vec<BucketSpec> {
+    vec![BucketSpec::new("sec", 60.0, Stage::Active),
+         BucketSpec::new("min", 60.0 * 60.0, Stage::Active),
+         BucketSpec::new("hr", DAY, Stage::Active),
+         BucketSpec::new("day", 30.0 * DAY, Stage::Learned),
+         BucketSpec::new("mon", 1.0e30, Stage::Learned)]
+}
+
+static BUCKETS: &'static str = "buckets";
+
+/// Read the buckets from the config table.
+pub fn get_buckets(conn: &Connection) -> Result<Vec<BucketSpec>> {
+    let text = match get_config(conn, BUCKETS)? {
+        None => return Ok(default_buckets()),
+        Some(text) => text,
+    };
+    let bad = || Error::InvalidConfig(format!("{}: {:?}", BUCKETS, text));
+
+    let mut buckets = vec![];
+    for field in text.split(',') {
+        let parts: Vec<_> = field.split(':').collect();
+        if parts.len() != 3 {
+            return Err(bad());
+        }
+        let limit = parts[1].parse().map_err(|_| bad())?;
+        let stage = match parts[2] {
+            "active" => Stage::Active,
+            "learned" => Stage::Learned,
+            _ => return Err(bad()),
+        };
+        buckets.push(BucketSpec::new(parts[0], limit, stage));
+    }
+    check_buckets(&buckets)?;
+    Ok(buckets)
+}
+
+/// Write the buckets to the config table.
+pub fn set_buckets(conn: &Connection, buckets: &[BucketSpec]) -> Result<()> {
+    check_buckets(buckets)?;
+    let fields: Vec<_> = buckets.iter()
+        .map(|b| {
+            format!("{}:{}:{}",
+                    b.name,
+                    b.limit,
+                    match b.stage {
+                        Stage::Active => "active",
+                        Stage::Learned => "learned",
+                    })
+        })
+        .collect();
+    set_config(conn, BUCKETS, &fields.join(","))
+}
+
+// Make sure there is at least one bucket, that the limits increase, and that the names can be
+// stored.
+fn check_buckets(buckets: &[BucketSpec]) -> Result<()> {
+    if buckets.is_empty() {
+        return Err(Error::InvalidConfig("no buckets".to_owned()));
+    }
+    let mut prior = 0.0;
+    for b in buckets {
+        if !(b.limit > prior) || !b.limit.is_finite() {
+            return Err(Error::InvalidConfig(format!("bucket limits must increase: {}", b.limit)));
+        }
+        if b.name.contains([',', ':']) {
+            return Err(Error::InvalidConfig(format!("bucket name {:?}", b.name)));
+        }
+        prior = b.limit;
+    }
+    Ok(())
+}
+
+/// Build an SQL expression giving the index of the bucket holding a problem with the interval
+/// `interval`.
+pub fn bucket_index(buckets: &[BucketSpec]) -> String {
+    // A CASE needs at least one WHEN.
+    if buckets.len() == 1 {
+        return "0".to_owned();
+    }
+    let mut expr = "CASE".to_owned();
+    for (i, b) in buckets.iter().enumerate().take(buckets.len() - 1) {
+        expr.push_str(&format!(" WHEN interval <= {:?} THEN {}", b.limit, i));
+    }
+    expr.push_str(&format!(" ELSE {} END", buckets.len() - 1));
+    expr
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_db;
+
+    #[test]
+    fn config_round_trip() {
+        let conn = test_db();
+        assert_eq!(get_buckets(&conn).unwrap(), default_buckets());
+
+        let buckets = vec![BucketSpec::new("young", 60.5, Stage::Active),
+                           BucketSpec::new("old", DAY, Stage::Learned)];
+        set_buckets(&conn, &buckets).unwrap();
+        assert_eq!(get_config(&conn, BUCKETS).unwrap(),
+                   Some("young:60.5:active,old:86400:learned".to_owned()));
+        assert_eq!(get_buckets(&conn).unwrap(), buckets);
+    }
+
+    #[test]
+    fn invalid() {
+        let a = |limit| BucketSpec::new("a", limit, Stage::Active);
+        assert!(check_buckets(&[]).is_err());
+        assert!(check_buckets(&[a(0.0)]).is_err());
+        assert!(check_buckets(&[a(10.0), a(10.0)]).is_err());
+        assert!(check_buckets(&[a(10.0), a(1.0 / 0.0)]).is_err());
+        assert!(check_buckets(&[BucketSpec::new("a:b", 10.0, Stage::Active)]).is_err());
+        assert!(check_buckets(&[a(10.0), a(20.0)]).is_ok());
+
+        let conn = test_db();
+        for text in &["", "a:10", "a:ten:active", "a:10:later", "a:10:active,b:5:learned"] {
+            set_config(&conn, BUCKETS, text).unwrap();
+            assert!(get_buckets(&conn).is_err(), "accepted {:?}", text);
+        }
+    }
+
+    // The expression should put each interval into the first bucket whose limit it is within, and
+    // anything longer into the last.
+    #[test]
+    fn index() {
+        let conn = test_db();
+        let index = |buckets: &[BucketSpec], interval: f64| -> i64 {
+            conn.query_row(&format!("SELECT {} FROM (SELECT ? AS interval)",
+                                    bucket_index(buckets)),
+                           &[&interval],
+                           |row| row.get(0))
+                .unwrap()
+        };
+        let buckets = default_buckets();
+        assert_eq!(index(&buckets, 5.0), 0);
+        assert_eq!(index(&buckets, 60.0), 0);
+        assert_eq!(index(&buckets, 61.0), 1);
+        assert_eq!(index(&buckets, DAY), 2);
+        assert_eq!(index(&buckets, 31.0 * DAY), 4);
+        assert_eq!(index(&buckets, 2.0e30), 4);
+
+        let buckets = [BucketSpec::new("all", 1.0, Stage::Active)];
+        assert_eq!(index(&buckets, 0.5), 0);
+        assert_eq!(index(&buckets, 2.0), 0);
+    }
+}
@@ -0,0 +1,53 @@
+//! Sources of the current time.
+//!
+//! The `Store` asks a `Clock` whenever it needs to know the current time.  Normally this is the
+//! `SystemClock`, but a `ManualClock` can be used to simulate the passage of time, such as when
+//! testing how problems are scheduled over a long period.
+
+use std::sync::{Arc, Mutex};
+
+/// A source of the current time, as a Posix timestamp.
+pub trait Clock {
+    /// Return the current time.
+    fn now(&self) -> f64;
+}
+
+/// A clock that reads the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        ::now()
+    }
+}
+
+/// A clock whose time only changes when it is told to.  Clones of a `ManualClock` share the same
+/// time, so a clone can be given to a `Store`, and the original used to advance time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    time: Arc<Mutex<f64>>,
+}
+
+impl ManualClock {
+    /// Create a new manual clock, set to the given time.
+    pub fn new(time: f64) -> ManualClock {
+        ManualClock { time: Arc::new(Mutex::new(time)) }
+    }
+
+    /// Set the clock to the given time.
+    pub fn set(&self, time: f64) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    /// Move the clock forward by the given number of seconds.
+    pub fn advance(&self, seconds: f64) {
+        *self.time.lock().unwrap() += seconds;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        *self.time.lock().unwrap()
+    }
+}
@@ -0,0 +1,360 @@
+//! Exporting and importing the entire contents of a `Store`.
+//!
+//! A `Dump` holds everything in a `Store`: its kind, its config, its profiles, and every problem,
+//! with its tags, and each profile's learning state, leeches and suspension, as well as the log of
+//! answers.  `Store::export` produces a `Dump`, and `Store::import` creates a new `Store` from one.
+//! Importing a dump produces a `Store` that is identical to the original, down to the problem ids.
+//!
+//! A `Dump` can be serialized with serde.  As JSON, it looks like:
+//!
+//! ```text
+//! {
+//!   "schema": "20261017J",
+//!   "kind": "simple",
+//!   "config": [{"key": "scheduler", "value": "multiplier"}, ...],
+//!   "profiles": ["default"],
+//!   "problems": [
+//!     {"id": 1, "question": "q1", "answer": "a1", "tags": ["left-hand"],
+//!      "learning": [{"profile": "default", "next": 1508261422.5, "interval": 300.0,
+//!                    "ease": 1.0, "lapses": 0, "phase": "review", "step": 0,
+//!                    "graduate": null}],
+//!      "imported": [],
+//!      "leeches": [],
+//!      "suspended": []},
+//!     ...
+//!   ],
+//!   "log": [{"stamp": 1508261122.5, "score": 3, "probid": 1, "profile": "default"}, ...]
+//! }
+//! ```
+//!
+//! `learning` holds the learning state of each profile that has learned the problem.  Its `phase`
+//! is `review`, `learning` or `relearning`, and `step` is the problem's place in its learning or
+//! relearning steps.  `graduate` is the interval a relearning problem returns to.
+//!
+//! `imported` holds, for each profile, the learning state that the problem was imported with, from
+//! `Populator::add_learning_problem`, which replaying the log starts from.
+//!
+//! `leeches` holds each profile that has detected the problem as a leech.
+//!
+//! `suspended` holds each profile that has suspended or buried the problem, with `until`, the
+//! time a buried problem returns, which is null for a problem suspended for good.
+
+use rusqlite::Connection;
+use {Error, Result, SCHEMA_VERSION};
+
+/// The complete contents of a `Store`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dump {
+    /// The schema version of the `Store` the dump was made from.
+    pub schema: String,
+    /// The kind of the `Store`.
+    pub kind: String,
+    /// All of the config settings, other than the kind.
+    pub config: Vec<ConfigEntry>,
+    /// The names of all of the profiles, in order.
+    pub profiles: Vec<String>,
+    /// All of the problems, in order of id.
+    pub problems: Vec<ProblemEntry>,
+    /// The log of every answer, in the order they were given.
+    pub log: Vec<LogEntry>,
+}
+
+/// A single config setting.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigEntry {
+    /// The name of the setting.
+    pub key: String,
+    /// The setting's value.
+    pub value: String,
+}
+
+/// A single problem.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProblemEntry {
+    /// The problem's id, referenced by the log.
+    pub id: i64,
+    /// The question.
+    pub question: String,
+    /// The answer.
+    pub answer: String,
+    /// The tags attached to the problem, sorted.
+    pub tags: Vec<String>,
+    /// The learning state of the problem, for each profile that has learned it, in order of
+    /// profile.
+    pub learning: Vec<LearningEntry>,
+    /// The learning state that the problem was imported with, for each profile that imported it,
+    /// in order of profile.
+    pub imported: Vec<ImportedEntry>,
+    /// The profiles that have detected the problem as a leech, in order.
+    pub leeches: Vec<String>,
+    /// The suspension of the problem, for each profile that has suspended or buried it, in order
+    /// of profile.
+    pub suspended: Vec<Suspension>,
+}
+
+/// The learning state of a problem for a single profile.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LearningEntry {
+    /// The profile that is learning the problem.
+    pub profile: String,
+    /// The time the problem should next be asked.
+    pub next: f64,
+    /// The current interval.
+    pub interval: f64,
+    /// The ease factor.
+    pub ease: f64,
+    /// The number of times the problem has been forgotten.
+    pub lapses: u32,
+    /// The phase of learning: `review`, `learning` or `relearning`.
+    pub phase: String,
+    /// The problem's place in its learning or relearning steps.
+    pub step: u32,
+    /// The interval a relearning problem will return to.
+    pub graduate: Option<f64>,
+}
+
+/// The learning state that a problem was imported with by a single profile.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImportedEntry {
+    /// The profile that imported the problem.
+    pub profile: String,
+    /// The time the problem was to be asked next.
+    pub next: f64,
+    /// The interval the problem was imported with.
+    pub interval: f64,
+}
+
+/// The suspension of a problem by a single profile.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Suspension {
+    /// The profile that suspended the problem.
+    pub profile: String,
+    /// The time that a buried problem can be asked again, or `None` if the problem is suspended
+    /// until it is unsuspended.
+    pub until: Option<f64>,
+}
+
+/// A single answer from the log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// The time the answer was given.
+    pub stamp: f64,
+    /// The grade given, 1..4.
+    pub score: u8,
+    /// The id of the problem answered.
+    pub probid: i64,
+    /// The profile that answered.
+    pub profile: String,
+}
+
+/// Read the entire contents of the database.
+pub fn save(conn: &Connection, kind: &str) -> Result<Dump> {
+    let mut config = vec![];
+    {
+        let mut stmt = conn.prepare("SELECT key, value FROM config WHERE key != 'kind'
+                                     ORDER BY key")?;
+        let rows = stmt.query_map(&[], |row| {
+                ConfigEntry {
+                    key: row.get(0),
+                    value: row.get(1),
+                }
+            })?;
+        for row in rows {
+            config.push(row?);
+        }
+    }
+
+    let mut profiles = vec![];
+    {
+        let mut stmt = conn.prepare("SELECT name FROM profiles ORDER BY name")?;
+        let rows = stmt.query_map(&[], |row| {
+                let name: String = row.get(0);
+                name
+            })?;
+        for row in rows {
+            profiles.push(row?);
+        }
+    }
+
+    let mut problems = vec![];
+    {
+        let mut stmt = conn.prepare("SELECT id, question, answer FROM probs ORDER BY id")?;
+        let rows = stmt.query_map(&[], |row| {
+                ProblemEntry {
+                    id: row.get(0),
+                    question: row.get(1),
+                    answer: row.get(2),
+                    tags: vec![],
+                    learning: vec![],
+                    imported: vec![],
+                    leeches: vec![],
+                    suspended: vec![],
+                }
+            })?;
+        for row in rows {
+            problems.push(row?);
+        }
+    }
+
+    {
+        let mut tags = conn.prepare("SELECT tag FROM tags WHERE probid = ? ORDER BY tag")?;
+        let mut learning = conn.prepare("
+            SELECT profile, next, interval, ease, lapses, phase, step, graduate
+            FROM learning
+            WHERE probid = ?
+            ORDER BY profile")?;
+        let mut imported = conn.prepare("
+            SELECT profile, next, interval
+            FROM imported
+            WHERE probid = ?
+            ORDER BY profile")?;
+        let mut leeches = conn.prepare("SELECT profile FROM leeches WHERE probid = ?
+                                        ORDER BY profile")?;
+        let mut suspended = conn.prepare("
+            SELECT profile, until
+            FROM suspended
+            WHERE probid = ?
+            ORDER BY profile")?;
+        for prob in &mut problems {
+            let rows = tags.query_map(&[&prob.id], |row| {
+                    let tag: String = row.get(0);
+                    tag
+                })?;
+            for row in rows {
+                prob.tags.push(row?);
+            }
+
+            let rows = learning.query_map(&[&prob.id], |row| {
+                    LearningEntry {
+                        profile: row.get(0),
+                        next: row.get(1),
+                        interval: row.get(2),
+                        ease: row.get(3),
+                        lapses: row.get(4),
+                        phase: row.get(5),
+                        step: row.get(6),
+                        graduate: row.get(7),
+                    }
+                })?;
+            for row in rows {
+                prob.learning.push(row?);
+            }
+
+            let rows = imported.query_map(&[&prob.id], |row| {
+                    ImportedEntry {
+                        profile: row.get(0),
+                        next: row.get(1),
+                        interval: row.get(2),
+                    }
+                })?;
+            for row in rows {
+                prob.imported.push(row?);
+            }
+
+            let rows = leeches.query_map(&[&prob.id], |row| {
+                    let profile: String = row.get(0);
+                    profile
+                })?;
+            for row in rows {
+                prob.leeches.push(row?);
+            }
+
+            let rows = suspended.query_map(&[&prob.id], |row| {
+                    Suspension {
+                        profile: row.get(0),
+                        until: row.get(1),
+                    }
+                })?;
+            for row in rows {
+                prob.suspended.push(row?);
+            }
+        }
+    }
+
+    let mut log = vec![];
+    {
+        let mut stmt = conn.prepare("SELECT stamp, score, probid, profile FROM log
+                                     ORDER BY rowid")?;
+        let rows = stmt.query_map(&[], |row| {
+                LogEntry {
+                    stamp: row.get(0),
+                    score: row.get(1),
+                    probid: row.get(2),
+                    profile: row.get(3),
+                }
+            })?;
+        for row in rows {
+            log.push(row?);
+        }
+    }
+
+    Ok(Dump {
+           schema: SCHEMA_VERSION.to_owned(),
+           kind: kind.to_owned(),
+           config: config,
+           profiles: profiles,
+           problems: problems,
+           log: log,
+       })
+}
+
+/// Write the contents of a dump into a newly created database.
+pub fn load(conn: &Connection, dump: &Dump) -> Result<()> {
+    if dump.schema != SCHEMA_VERSION {
+        return Err(Error::SchemaMismatch(dump.schema.clone()));
+    }
+
+    for ent in &dump.config {
+        conn.execute("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)",
+                     &[&ent.key, &ent.value])?;
+    }
+
+    // A new database already has the default profile.
+    for name in &dump.profiles {
+        conn.execute("INSERT OR IGNORE INTO profiles (name) VALUES (?)", &[name])?;
+    }
+
+    for prob in &dump.problems {
+        conn.execute("INSERT INTO probs (id, question, answer) VALUES (?, ?, ?)",
+                     &[&prob.id, &prob.question, &prob.answer])?;
+        for tag in &prob.tags {
+            conn.execute("INSERT INTO tags (probid, tag) VALUES (?, ?)",
+                         &[&prob.id, tag])?;
+        }
+        for learn in &prob.learning {
+            conn.execute("INSERT INTO learning
+                              (profile, probid, next, interval, ease, lapses, phase, step,
+                               graduate)
+                          VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                         &[&learn.profile,
+                           &prob.id,
+                           &learn.next,
+                           &learn.interval,
+                           &learn.ease,
+                           &learn.lapses,
+                           &learn.phase,
+                           &learn.step,
+                           &learn.graduate])?;
+        }
+        for imp in &prob.imported {
+            conn.execute("INSERT INTO imported (profile, probid, next, interval)
+                          VALUES (?, ?, ?, ?)",
+                         &[&imp.profile, &prob.id, &imp.next, &imp.interval])?;
+        }
+        for profile in &prob.leeches {
+            conn.execute("INSERT INTO leeches (profile, probid) VALUES (?, ?)",
+                         &[profile, &prob.id])?;
+        }
+        for susp in &prob.suspended {
+            conn.execute("INSERT INTO suspended (profile, probid, until) VALUES (?, ?, ?)",
+                         &[&susp.profile, &prob.id, &susp.until])?;
+        }
+    }
+
+    for ent in &dump.log {
+        conn.execute("INSERT INTO log (stamp, score, probid, profile) VALUES (?, ?, ?, ?)",
+                     &[&ent.stamp, &ent.score, &ent.probid, &ent.profile])?;
+    }
+
+    Ok(())
+}
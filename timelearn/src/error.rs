@@ -0,0 +1,108 @@
+//! Errors that can happen while learning.
+
+use rusqlite;
+use std::error;
+use std::fmt;
+use std::io;
+
+/// The errors returned by this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// The database has a schema version that this crate doesn't know how to use.
+    SchemaMismatch(String),
+    /// The database's schema version is missing or malformed.
+    BadSchema(&'static str),
+    /// The database doesn't record what kind of problems it holds.
+    MissingKind,
+    /// There is no problem with the given question.
+    UnknownProblem(String),
+    /// The same problem was given more than once.
+    DuplicateProblem(String),
+    /// There is no profile with the given name.
+    UnknownProfile(String),
+    /// Another session has the database open for writing.
+    Locked(String),
+    /// A grade outside of the range 1..4 was given.
+    InvalidGrade(u8),
+    /// A value in the config table could not be understood.
+    InvalidConfig(String),
+    /// The answer of a problem could not be decoded.
+    CorruptAnswer(String),
+    /// The user's input ended while waiting for an answer.
+    EndOfInput,
+    /// An error from a `User` implementation, such as a failure of its input device.
+    User(Box<error::Error + Send + Sync>),
+    /// An I/O error.
+    Io(io::Error),
+    /// An error from the sqlite database.
+    Sql(rusqlite::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::SchemaMismatch(ref version) => {
+                write!(f, "Unsupported database schema version {:?}", version)
+            }
+            Error::BadSchema(msg) => write!(f, "Invalid database schema: {}", msg),
+            Error::MissingKind => write!(f, "Database does not record a problem kind"),
+            Error::UnknownProblem(ref question) => write!(f, "No problem {:?}", question),
+            Error::DuplicateProblem(ref question) => {
+                write!(f, "Problem {:?} given more than once", question)
+            }
+            Error::UnknownProfile(ref name) => write!(f, "No profile {:?}", name),
+            Error::Locked(ref path) => {
+                write!(f, "Database {} is in use by another session", path)
+            }
+            Error::InvalidGrade(grade) => write!(f, "Invalid grade: {} (expecting 1..4)", grade),
+            Error::InvalidConfig(ref msg) => write!(f, "Invalid config: {}", msg),
+            Error::CorruptAnswer(ref msg) => write!(f, "Corrupt answer: {}", msg),
+            Error::EndOfInput => write!(f, "End of input"),
+            Error::User(ref err) => err.fmt(f),
+            Error::Io(ref err) => err.fmt(f),
+            Error::Sql(ref err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::SchemaMismatch(_) => "unsupported schema version",
+            Error::BadSchema(msg) => msg,
+            Error::MissingKind => "no problem kind",
+            Error::UnknownProblem(_) => "unknown problem",
+            Error::DuplicateProblem(_) => "duplicate problem",
+            Error::UnknownProfile(_) => "unknown profile",
+            Error::Locked(_) => "database in use",
+            Error::InvalidGrade(_) => "invalid grade",
+            Error::InvalidConfig(_) => "invalid config",
+            Error::CorruptAnswer(_) => "corrupt answer",
+            Error::EndOfInput => "end of input",
+            Error::User(_) => "user error",
+            Error::Io(_) => "I/O error",
+            Error::Sql(_) => "database error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::User(ref err) => Some(&**err),
+            Error::Io(ref err) => Some(err),
+            Error::Sql(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Sql(err)
+    }
+}
@@ -0,0 +1,172 @@
+//! Forecasting the reviews that will come due.
+
+use rusqlite::Connection;
+use {Result, AVAILABLE, DAY};
+
+/// The length of the periods of a `Forecast`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Granularity {
+    /// Calendar hours, for the near term.
+    Hours,
+    /// Calendar days.
+    Days,
+}
+
+impl Granularity {
+    /// The length of a period, in seconds.
+    pub fn length(&self) -> f64 {
+        match *self {
+            Granularity::Hours => 60.0 * 60.0,
+            Granularity::Days => DAY,
+        }
+    }
+}
+
+/// The number of problems that will come due in each of a number of periods, from
+/// `Store::get_forecast`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Forecast {
+    /// The length of the periods.
+    pub granularity: Granularity,
+    /// The number of problems that are already due.
+    pub overdue: usize,
+    /// The periods, in order, starting with the one holding the current time.
+    pub periods: Vec<Period>,
+}
+
+/// The problems coming due in a single period.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Period {
+    /// The time the period starts.  The first period starts before the current time.
+    pub start: f64,
+    /// The number of problems that come due in the period.
+    pub count: usize,
+}
+
+/// Count the problems due in `count` periods of the given granularity, starting with the period
+/// holding `cur`, which starts at `start`.  The tag filter is `tag`, and the problems are those
+/// learned by `profile`.
+pub fn compute(conn: &Connection,
+               tag: &Option<String>,
+               profile: &str,
+               cur: f64,
+               start: f64,
+               granularity: Granularity,
+               count: usize)
+               -> Result<Forecast> {
+    let length = granularity.length();
+    let mut periods: Vec<_> = (0..count)
+        .map(|i| {
+            Period {
+                start: start + i as f64 * length,
+                count: 0,
+            }
+        })
+        .collect();
+    let end = start + count as f64 * length;
+
+    let overdue: i64 = conn.query_row(&format!("
+        SELECT COUNT(*)
+        FROM probs JOIN learning
+        WHERE probs.id = learning.probid
+            AND learning.profile = ?3
+            AND next <= ?1
+            {}",
+                                               AVAILABLE),
+                                      &[&cur, tag, &profile],
+                                      |row| row.get(0))?;
+
+    let mut stmt = conn.prepare(&format!("
+        SELECT next
+        FROM probs JOIN learning
+        WHERE probs.id = learning.probid
+            AND learning.profile = ?3
+            AND next > ?1 AND next < ?4
+            {}",
+                                         AVAILABLE))?;
+    let rows = stmt.query_map(&[&cur, tag, &profile, &end], |row| {
+            let next: f64 = row.get(0);
+            next
+        })?;
+    for next in rows {
+        let pos = ((next? - start) / length).floor() as usize;
+        if pos < periods.len() {
+            periods[pos].count += 1;
+        }
+    }
+
+    Ok(Forecast {
+           granularity: granularity,
+           overdue: overdue as usize,
+           periods: periods,
+       })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_db;
+
+    // Noon of a day, and the start of that day.
+    const CUR: f64 = 1.0e9 - 1.0e9 % DAY + DAY / 2.0;
+    const START: f64 = CUR - DAY / 2.0;
+
+    // A database holding problems with the given profile and next time.  Problems 2 and 4 are
+    // tagged "odd", problem 7 is suspended, and problem 8 was buried until before `CUR`.
+    fn learning(probs: &[(&str, f64)]) -> Connection {
+        let conn = test_db();
+        conn.execute_batch("
+            INSERT INTO tags (probid, tag) VALUES (2, 'odd'), (4, 'odd');
+            INSERT INTO suspended (profile, probid, until) VALUES ('default', 7, NULL);")
+            .unwrap();
+        conn.execute("INSERT INTO suspended (profile, probid, until) VALUES ('default', 8, ?)",
+                     &[&(CUR - 1.0)])
+            .unwrap();
+        for (i, &(profile, next)) in probs.iter().enumerate() {
+            let id = i as i64 + 1;
+            conn.execute("INSERT INTO probs (id, question, answer) VALUES (?, ?, '')",
+                         &[&id, &format!("q{}", id)])
+                .unwrap();
+            conn.execute("INSERT INTO learning (profile, probid, next, interval)
+                          VALUES (?, ?, ?, 60.0)",
+                         &[&profile, &id, &next])
+                .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn length() {
+        assert_eq!(Granularity::Hours.length(), 3600.0);
+        assert_eq!(Granularity::Days.length(), DAY);
+    }
+
+    #[test]
+    fn periods() {
+        let conn = learning(&[("default", CUR - 100.0),
+                              ("default", CUR + 30.0),
+                              ("default", CUR + 3600.0),
+                              ("default", START + DAY),
+                              ("default", START + 3.0 * DAY),
+                              ("other", CUR + 30.0),
+                              ("default", CUR + 40.0),
+                              ("default", CUR + 50.0)]);
+
+        // The last problem is at the end of the last period, so isn't counted.
+        let fc = compute(&conn, &None, "default", CUR, START, Granularity::Days, 3).unwrap();
+        assert_eq!(fc.overdue, 1);
+        let periods: Vec<_> = fc.periods.iter().map(|p| (p.start, p.count)).collect();
+        assert_eq!(periods,
+                   vec![(START, 3), (START + DAY, 1), (START + 2.0 * DAY, 0)]);
+
+        let fc = compute(&conn, &None, "default", CUR, CUR, Granularity::Hours, 2).unwrap();
+        let counts: Vec<_> = fc.periods.iter().map(|p| p.count).collect();
+        assert_eq!(counts, vec![2, 1]);
+
+        let odd = Some("odd".to_owned());
+        let fc = compute(&conn, &odd, "default", CUR, START, Granularity::Days, 3).unwrap();
+        assert_eq!(fc.overdue, 0);
+        let counts: Vec<_> = fc.periods.iter().map(|p| p.count).collect();
+        assert_eq!(counts, vec![1, 1, 0]);
+    }
+}
@@ -0,0 +1,76 @@
+//! Leech detection.
+//!
+//! A leech is a problem that keeps being forgotten.  Each time a problem that has already been
+//! learned is answered incorrectly, its lapse count is incremented.  Once the lapse count reaches
+//! the threshold in the `LeechPolicy`, the problem is marked as a leech, and, if the policy says
+//! so, suspended, so that the problem can be looked at and fixed.
+//!
+//! Lapses are counted separately for each profile, so a problem is only a leech for the profiles
+//! that keep forgetting it.  To the tag filter, a profile's leeches have the tag `LEECH_TAG`.
+
+use rusqlite::Connection;
+use {get_config, set_config, Error, Result};
+
+/// The tag given to problems that have been detected as leeches.
+pub const LEECH_TAG: &'static str = "leech";
+
+/// How leeches are detected and handled.  This is kept in the `config` table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LeechPolicy {
+    /// The number of lapses after which a problem is considered a leech.
+    pub threshold: u32,
+    /// Whether leeches should be suspended when they are detected.
+    pub suspend: bool,
+}
+
+impl Default for LeechPolicy {
+    fn default() -> LeechPolicy {
+        LeechPolicy {
+            threshold: 8,
+            suspend: false,
+        }
+    }
+}
+
+/// A problem that has been detected as a leech.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leech {
+    /// The question of the problem.
+    pub question: String,
+    /// The answer of the problem.
+    pub answer: String,
+    /// The number of times the problem has been forgotten.
+    pub lapses: u32,
+    /// Whether the problem is currently suspended.
+    pub suspended: bool,
+}
+
+static LEECH_THRESHOLD: &'static str = "leech-threshold";
+static LEECH_SUSPEND: &'static str = "leech-suspend";
+
+/// Read the leech policy from the config table.
+pub fn get_policy(conn: &Connection) -> Result<LeechPolicy> {
+    let mut policy = LeechPolicy::default();
+    if let Some(text) = get_config(conn, LEECH_THRESHOLD)? {
+        policy.threshold = match text.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                return Err(Error::InvalidConfig(format!("{}: {:?}", LEECH_THRESHOLD, text)))
+            }
+        };
+    }
+    if let Some(text) = get_config(conn, LEECH_SUSPEND)? {
+        policy.suspend = match text.parse() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::InvalidConfig(format!("{}: {:?}", LEECH_SUSPEND, text))),
+        };
+    }
+    Ok(policy)
+}
+
+/// Write the leech policy to the config table.
+pub fn set_policy(conn: &Connection, policy: &LeechPolicy) -> Result<()> {
+    set_config(conn, LEECH_THRESHOLD, &policy.threshold.to_string())?;
+    set_config(conn, LEECH_SUSPEND, &policy.suspend.to_string())?;
+    Ok(())
+}
@@ -12,23 +12,67 @@
 //! The client of this library should be able to ask questions, determine if the answer is correct,
 //! and return a 1-4 rating of how well the user answered the question.  In some cases, it may only
 //! make sense to return either a 1 for an incorrect answer, or a 4 for a correct answer.
+//!
+//! How the intervals grow in response to these ratings is determined by a `Scheduler`, which is
+//! recorded, along with its parameters, in each database.
+//!
+//! A database can be shared by several people, each with their own profile.  The problems are
+//! shared, but each profile keeps its own learning state, suspensions and log.
 
 #![deny(missing_docs)]
 
 extern crate rand;
 extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 
-use rand::{Rng, thread_rng};
-use rusqlite::{Connection, Transaction};
-use std::error;
+use rand::{thread_rng, Rng};
+use lock::SessionLock;
+use rusqlite::{Connection, Transaction, SQLITE_OPEN_NO_MUTEX, SQLITE_OPEN_READ_ONLY};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::result;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-/// A wrapper around the result type for all results returned.  Currently, the errors are just
-/// boxed, and this should be improved.
-pub type Result<T> = result::Result<T, Box<error::Error + Send + Sync>>;
+pub use buckets::{BucketSpec, Stage};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use dump::Dump;
+pub use error::Error;
+pub use forecast::{Forecast, Granularity};
+pub use leech::{Leech, LeechPolicy, LEECH_TAG};
+pub use limits::{Budget, Limits};
+pub use merge::MergeReport;
+pub use migrate::SCHEMA_VERSION;
+pub use optimize::Fit;
+pub use replay::Rebuilt;
+pub use rollover::Rollover;
+pub use scheduler::{Fsrs, Multiplier, Schedule, Scheduler, Sm2, INITIAL_EASE};
+pub use stats::Stats;
+pub use steps::{Phase, Steps};
+pub use sync::{Missing, SyncReport, Syncer, RETIRED_TAG};
+
+mod backup;
+mod buckets;
+pub mod clock;
+pub mod dump;
+mod error;
+mod forecast;
+mod leech;
+mod limits;
+mod lock;
+mod merge;
+mod migrate;
+mod optimize;
+mod replay;
+mod rollover;
+pub mod scheduler;
+pub mod stats;
+mod steps;
+mod sync;
+
+/// A wrapper around the result type for all results returned.
+pub type Result<T> = result::Result<T, Error>;
 
 /// A Store holds problems in a database (and holds the handle to the database).
 pub struct Store {
@@ -37,33 +81,77 @@ pub struct Store {
 
     /// The kind, which indicates how the problems are interpreted.
     kind: String,
+
+    /// The scheduler used to compute new intervals.
+    scheduler: Box<Scheduler>,
+
+    /// The source of the current time.
+    clock: Box<Clock>,
+
+    /// Limits on new problems and reviews.
+    limits: Limits,
+
+    /// The number of problems updated since this Store was opened.
+    session_reviews: usize,
+
+    /// If set, only problems with this tag will be asked or counted.
+    tag: Option<String>,
+
+    /// How leeches are detected and handled.
+    leech_policy: LeechPolicy,
+
+    /// The histogram buckets used by `get_counts`.
+    buckets: Vec<BucketSpec>,
+
+    /// The learning steps problems go through before they are scheduled.
+    steps: Steps,
+
+    /// The profile whose learning state and log are used.
+    profile: String,
+
+    /// When days start.
+    rollover: Rollover,
+
+    /// The session lock, held unless the Store was opened read-only.  This is dropped after the
+    /// connection is closed.
+    _lock: Option<SessionLock>,
 }
 
+/// The smallest interval, in seconds, that a problem will be scheduled with.
+pub const MIN_INTERVAL: f64 = 5.0;
+
+// The number of seconds in a day.
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// The profile that a `Store` uses when it is opened, which every database has.
+pub const DEFAULT_PROFILE: &'static str = "default";
+
+// Conditions restricting queries on `probs` to the problems that can currently be asked: those
+// with the filter tag (if there is one), and that the profile hasn't suspended.  Queries using
+// this must bind the current time as ?1, the tag filter as ?2, and the profile as ?3.  Tags are
+// matched through `profile_tags`, which adds the profile's leeches as tagged with `LEECH_TAG`.
+const AVAILABLE: &'static str = "
+    AND (?2 IS NULL OR probs.id IN (SELECT probid FROM profile_tags
+                                    WHERE profile = ?3 AND tag = ?2))
+    AND probs.id NOT IN (SELECT probid FROM suspended
+        WHERE profile = ?3 AND (until IS NULL OR until > ?1))";
+
 impl Store {
     /// Create a new store at the given path.  Will return an error if the database has already
     /// been created.  The `kind` is a string that can be used later to determine what kind of user
     /// interaction to use (and define the interpretation of the problems).
     pub fn create<P: AsRef<Path>>(path: P, kind: &str) -> Result<Store> {
+        let path = path.as_ref();
+        let lock = SessionLock::acquire(path)?;
         let mut conn = Connection::open(path)?;
+        set_wal(&conn)?;
 
         {
             let tx = conn.transaction()?;
-            tx.execute("CREATE TABLE probs (id INTEGER PRIMARY KEY,
-                question TEXT UNIQUE,
-                answer TEXT NOT NULL)",
-                         &[])?;
-            tx.execute("CREATE TABLE learning (probid INTEGER PRIMARY KEY REFERENCES probs (id),
-                next REAL NOT NULL,
-                interval REAL NOT NULL)",
-                         &[])?;
-            tx.execute("CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT NOT NULL)", &[])?;
+            migrate::create_base(&tx)?;
+            migrate::apply(&tx, migrate::BASE_VERSION)?;
             tx.execute("INSERT INTO config VALUES ('kind', ?)", &[&kind])?;
-            tx.execute("CREATE INDEX learning_next ON learning (next)", &[])?;
-            tx.execute("CREATE TABLE schema_version (version TEXT NOT NULL)", &[])?;
-            tx.execute("CREATE TABLE log (stamp REAL NOT NULL,
-                score INTEGER NOT NULL,
-                probid INTEGER REFERENCES probs (id) NOT NULL)", &[])?;
-            tx.execute("INSERT INTO schema_version VALUES (?)", &[&"20170709A"])?;
+            set_scheduler_config(&tx, &Multiplier::default())?;
             tx.commit()?;
         }
 
@@ -72,91 +160,496 @@ impl Store {
         Ok(Store {
             conn: conn,
             kind: kind.to_owned(),
+            scheduler: Box::new(Multiplier::default()),
+            clock: Box::new(SystemClock),
+            limits: Limits::default(),
+            session_reviews: 0,
+            tag: None,
+            leech_policy: LeechPolicy::default(),
+            buckets: buckets::default_buckets(),
+            steps: Steps::default(),
+            profile: DEFAULT_PROFILE.to_owned(),
+            rollover: Rollover::default(),
+            _lock: Some(lock),
         })
     }
 
-    /// Open an existing (and ideally already populated) `Store`.
+    /// Open an existing (and ideally already populated) `Store`.  If the database uses an older
+    /// schema, it will be upgraded, after first saving a copy of the file alongside it, named with
+    /// the old schema version and a `.bak` suffix.  Only one session can have a database open
+    /// like this at a time; if another one does, this fails with `Error::Locked`.  The lock is
+    /// held on a file alongside the database, named with a `-session` suffix, which is left in
+    /// place when the `Store` is dropped.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Store> {
-        let conn = Connection::open(path)?;
-        {
-            let mut stmt = conn.prepare(" SELECT version FROM schema_version")?;
-            let mut rows = stmt.query_map(&[], |row| {
-                    let vers: String = row.get(0);
-                    vers
-                })?;
-            match rows.next() {
-                Some(text) => {
-                    let text = text?;
-                    if text != "20170709A" {
-                        panic!("schema version mismatch {}", text);
-                    }
-                }
-                None => panic!("No schema present"),
-            }
-            match rows.next() {
-                Some(_) => panic!("Multiple rows in schema_version"),
-                None => (),
-            }
+        let path = path.as_ref();
+        let lock = SessionLock::acquire(path)?;
+        let mut conn = Connection::open(path)?;
+        migrate::upgrade(&mut conn, path)?;
+        set_wal(&conn)?;
+        Store::load(conn, Some(lock))
+    }
+
+    /// Open an existing `Store` for reading, without the session lock, so that it can be used
+    /// while another session has the database open.  Nothing in the database can be changed, and
+    /// it must already use the current schema.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Store> {
+        let conn = Connection::open_with_flags(path,
+                                               SQLITE_OPEN_READ_ONLY | SQLITE_OPEN_NO_MUTEX)?;
+        let version = migrate::get_version(&conn)?;
+        if version != SCHEMA_VERSION {
+            return Err(Error::SchemaMismatch(version));
         }
-        let kind = {
-            let mut stmt = conn.prepare("SELECT value FROM config WHERE key = 'kind'")?;
-            let mut rows = stmt.query_map(&[], |row| {
-                let value: String = row.get(0);
-                value
-            })?;
-            match rows.next() {
-                Some(text) => text?,
-                None => panic!("No kind present"),
-            }
+        Store::load(conn, None)
+    }
+
+    // Build a Store around an open connection, reading its configuration.
+    fn load(conn: Connection, lock: Option<SessionLock>) -> Result<Store> {
+        let kind = match get_config(&conn, "kind")? {
+            Some(text) => text,
+            None => return Err(Error::MissingKind),
         };
+        let scheduler = get_scheduler_config(&conn)?;
+        let limits = limits::get_limits(&conn)?;
+        let leech_policy = leech::get_policy(&conn)?;
+        let buckets = buckets::get_buckets(&conn)?;
+        let steps = steps::get_steps(&conn)?;
+        let rollover = rollover::get_rollover(&conn)?;
         Ok(Store {
             conn: conn,
             kind: kind,
+            scheduler: scheduler,
+            clock: Box::new(SystemClock),
+            limits: limits,
+            session_reviews: 0,
+            tag: None,
+            leech_policy: leech_policy,
+            buckets: buckets,
+            steps: steps,
+            profile: DEFAULT_PROFILE.to_owned(),
+            rollover: rollover,
+            _lock: lock,
         })
     }
 
+    /// Create a new `Store` at `path` holding the contents of `dump`, which will typically come
+    /// from `export`.  The dump must have been made with the current schema version.
+    pub fn import<P: AsRef<Path>>(path: P, dump: &Dump) -> Result<Store> {
+        let path = path.as_ref();
+        {
+            let mut st = Store::create(path, &dump.kind)?;
+            let tx = st.conn.transaction()?;
+            dump::load(&tx, dump)?;
+            tx.commit()?;
+        }
+        Store::open(path)
+    }
+
+    /// Retrieve the entire contents of this `Store`.
+    pub fn export(&self) -> Result<Dump> {
+        dump::save(&self.conn, &self.kind)
+    }
+
+    /// Copy the database, with SQLite's online backup API, into a directory alongside it named
+    /// with a `.backups` suffix, and remove the oldest copies beyond `get_backup_count`.  Returns
+    /// the name of the copy.
+    pub fn backup(&self) -> Result<PathBuf> {
+        backup::backup(&self.conn, self.now())
+    }
+
+    /// List the backups of the database at `path` made by `backup`, oldest first.
+    pub fn backups<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+        backup::list(path.as_ref())
+    }
+
+    /// Replace the contents of the database at `path` with the backup `backup`, first backing up
+    /// the contents being replaced.  This fails with `Error::Locked` if another session has the
+    /// database open.  Returns the name of the backup of the replaced contents.
+    pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(path: P, backup: Q) -> Result<PathBuf> {
+        backup::restore(path.as_ref(), backup.as_ref(), now())
+    }
+
+    /// Retrieve the number of backups that are kept.
+    pub fn get_backup_count(&self) -> Result<usize> {
+        backup::get_count(&self.conn)
+    }
+
+    /// Change the number of backups that are kept.  This is saved in the database, and takes
+    /// effect the next time a backup is made.
+    pub fn set_backup_count(&mut self, count: usize) -> Result<()> {
+        backup::set_count(&self.conn, count)
+    }
+
+    /// Merge the answers in `other`, which is typically exported from another copy of this
+    /// database, into this one.  Problems are matched by question.  Answers that this database
+    /// already has are skipped, and the learning state of each problem that gains answers is
+    /// recomputed from its log with this database's scheduler and steps.
+    pub fn merge(&mut self, other: &Dump) -> Result<MergeReport> {
+        let tx = self.conn.transaction()?;
+        let report = merge::merge(&tx, &*self.scheduler, &self.steps, &self.rollover, other)?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Recompute the learning state of every problem the profile has answered by replaying its
+    /// log through the scheduler and steps, which may differ from those that originally scheduled
+    /// it.  If `write` is set, the learning state is replaced with the replayed state, otherwise
+    /// nothing is changed, which shows what a different scheduler would have done with the same
+    /// history.  Problems that were imported with learning state replay from that state, and
+    /// problems with no answers in the log since they were imported keep their learning state.
+    pub fn rebuild(&mut self, write: bool) -> Result<Vec<Rebuilt>> {
+        let tx = self.conn.transaction()?;
+        let result = replay::rebuild_all(&tx,
+                                         &*self.scheduler,
+                                         &self.steps,
+                                         &self.rollover,
+                                         &self.profile,
+                                         write)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// Fit the parameters of the scheduler to the log of every profile, choosing those under which
+    /// the scheduler's `recall` makes the recorded answers most likely.  Only the scheduler's
+    /// `tunable` parameters are fitted, and nothing is changed; the fitted parameters can be used
+    /// by giving a scheduler built from them to `set_scheduler`.
+    pub fn optimize(&self) -> Result<Fit> {
+        optimize::optimize(&self.conn, &*self.scheduler, &self.steps, &self.rollover)
+    }
+
     /// Retrieve the kind of this Store.  This is the string given when the store was created,
     /// indicates how the problems should be interpreted.
     pub fn get_kind(&self) -> &str {
         &self.kind
     }
 
+    /// Retrieve the scheduler used by this Store.
+    pub fn get_scheduler(&self) -> &Scheduler {
+        &*self.scheduler
+    }
+
+    /// Change the scheduler used by this Store.  The scheduler's name and parameters are saved in
+    /// the database, and will be used the next time it is opened.
+    pub fn set_scheduler(&mut self, scheduler: Box<Scheduler>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        set_scheduler_config(&tx, &*scheduler)?;
+        tx.commit()?;
+        self.scheduler = scheduler;
+        Ok(())
+    }
+
+    /// Retrieve the limits on new problems and reviews.
+    pub fn get_limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Change the limits on new problems and reviews.  The limits are saved in the database.
+    pub fn set_limits(&mut self, limits: Limits) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        limits::set_limits(&tx, &limits)?;
+        tx.commit()?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Determine how much of the limits remain.  A session is the lifetime of this Store, and days
+    /// start as given by `get_rollover`.  This is the budget that `get_nexts` is limited by, and
+    /// that `get_counts` reports.
+    pub fn get_budget(&self) -> Result<Budget> {
+        let new_left = match self.limits.new_per_day {
+            None => None,
+            Some(limit) => {
+                let start = self.day_start();
+                // A problem was new today if its first answer was today.
+                let new_today: i64 = self.conn
+                    .query_row("
+                        SELECT COUNT(*) FROM (
+                            SELECT probid FROM log
+                            WHERE profile = ?
+                            GROUP BY probid
+                            HAVING MIN(stamp) >= ?)",
+                               &[&self.profile, &start],
+                               |row| row.get(0))?;
+                Some(limit.saturating_sub(new_today as usize))
+            }
+        };
+        let reviews_left = self.limits
+            .reviews_per_session
+            .map(|limit| limit.saturating_sub(self.session_reviews));
+        Ok(Budget {
+               new_left: new_left,
+               reviews_left: reviews_left,
+           })
+    }
+
+    /// Retrieve the policy for detecting and handling leeches.
+    pub fn get_leech_policy(&self) -> LeechPolicy {
+        self.leech_policy
+    }
+
+    /// Change the policy for detecting and handling leeches.  The policy is saved in the
+    /// database.  Problems are only checked against the policy when they lapse, so this does not
+    /// affect problems that have already been detected as leeches.
+    pub fn set_leech_policy(&mut self, policy: LeechPolicy) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        leech::set_policy(&tx, &policy)?;
+        tx.commit()?;
+        self.leech_policy = policy;
+        Ok(())
+    }
+
+    /// Retrieve the histogram buckets used by `get_counts` and `get_stats`.
+    pub fn get_buckets(&self) -> &[BucketSpec] {
+        &self.buckets
+    }
+
+    /// Change the histogram buckets used by `get_counts` and `get_stats`.  The buckets are saved
+    /// in the database.  There must be at least one bucket, and their limits must increase.
+    pub fn set_buckets(&mut self, buckets: Vec<BucketSpec>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        buckets::set_buckets(&tx, &buckets)?;
+        tx.commit()?;
+        self.buckets = buckets;
+        Ok(())
+    }
+
+    /// Retrieve the learning and relearning steps.
+    pub fn get_steps(&self) -> &Steps {
+        &self.steps
+    }
+
+    /// Change the learning and relearning steps.  The steps are saved in the database.  Problems
+    /// already in their steps keep their place, and graduate if there are no longer enough steps.
+    pub fn set_steps(&mut self, steps: Steps) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        steps::set_steps(&tx, &steps)?;
+        tx.commit()?;
+        self.steps = steps;
+        Ok(())
+    }
+
+    /// Retrieve when days start.
+    pub fn get_rollover(&self) -> Rollover {
+        self.rollover
+    }
+
+    /// Change when days start.  This is saved in the database.  Problems that are already
+    /// scheduled keep their due times until they are next answered.  The UTC offset is used as
+    /// given all year round, so where the clocks change for daylight saving time, it has to be set
+    /// again each time for days to keep starting at the same local hour.
+    pub fn set_rollover(&mut self, rollover: Rollover) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        rollover::set_rollover(&tx, &rollover)?;
+        tx.commit()?;
+        self.rollover = rollover;
+        Ok(())
+    }
+
+    /// Retrieve all of the problems that the profile has detected as leeches, with the most lapses
+    /// first.
+    pub fn get_leeches(&self) -> Result<Vec<Leech>> {
+        let mut stmt = self.conn
+            .prepare("
+            SELECT question, answer, lapses,
+                probs.id IN (SELECT probid FROM suspended WHERE profile = ?1 AND until IS NULL)
+            FROM probs JOIN learning JOIN leeches
+            WHERE probs.id = learning.probid
+                AND probs.id = leeches.probid
+                AND learning.profile = ?1
+                AND leeches.profile = ?1
+            ORDER BY lapses DESC, probs.id")?;
+        let rows = stmt.query_map(&[&self.profile], |row| {
+                Leech {
+                    question: row.get(0),
+                    answer: row.get(1),
+                    lapses: row.get(2),
+                    suspended: row.get(3),
+                }
+            })?;
+        let mut result = vec![];
+        for row in rows {
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// Suspend a problem, so that it won't be asked until `unsuspend` is called.  Its learning
+    /// state is kept as it is.
+    pub fn suspend(&mut self, prob: &Problem) -> Result<()> {
+        self.conn.execute("INSERT OR REPLACE INTO suspended (profile, probid, until)
+                           VALUES (?, ?, NULL)",
+                          &[&self.profile, &prob.id])?;
+        Ok(())
+    }
+
+    /// Bury a problem, so that it won't be asked again until tomorrow.  Burying a problem that is
+    /// suspended has no effect.
+    pub fn bury(&mut self, prob: &Problem) -> Result<()> {
+        let until = self.day_start() + DAY;
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM suspended WHERE profile = ? AND probid = ? AND until IS NOT NULL",
+                   &[&self.profile, &prob.id])?;
+        tx.execute("INSERT OR IGNORE INTO suspended (profile, probid, until) VALUES (?, ?, ?)",
+                   &[&self.profile, &prob.id, &until])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Return a suspended or buried problem to the set of problems that can be asked.  Since
+    /// suspended problems are never returned by this `Store`, the problem is given by its
+    /// question.  Returns `Error::UnknownProblem` if there is no problem with that question.
+    pub fn unsuspend(&mut self, question: &str) -> Result<()> {
+        let id: i64 = match self.conn
+                  .query_row("SELECT id FROM probs WHERE question = ?",
+                             &[&question],
+                             |row| row.get(0)) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(Error::UnknownProblem(question.to_owned()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.conn.execute("DELETE FROM suspended WHERE profile = ? AND probid = ?",
+                          &[&self.profile, &id])?;
+        Ok(())
+    }
+
+    /// Restrict the problems asked by `get_nexts` and `get_new`, and counted by `get_counts`, to
+    /// those with the given tag.  A tag of `None` removes the restriction.  The filter is not
+    /// saved in the database.  The profile's leeches have the tag `LEECH_TAG`.
+    pub fn set_tag_filter(&mut self, tag: Option<&str>) {
+        self.tag = tag.map(|t| t.to_owned());
+    }
+
+    /// Retrieve the current tag filter.
+    pub fn get_tag_filter(&self) -> Option<&str> {
+        self.tag.as_ref().map(|t| &t[..])
+    }
+
+    /// Retrieve the names of all of the profiles in the database, in order.
+    pub fn get_profiles(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT name FROM profiles ORDER BY name")?;
+        let rows = stmt.query_map(&[], |row| {
+                let name: String = row.get(0);
+                name
+            })?;
+        let mut result = vec![];
+        for row in rows {
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// Add a new profile, which starts out having learned nothing, and with the problems retired
+    /// by a sync suspended.  Adding a profile that already exists has no effect.
+    pub fn add_profile(&mut self, name: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        if tx.execute("INSERT OR IGNORE INTO profiles (name) VALUES (?)", &[&name])? > 0 {
+            sync::suspend_retired(&tx, name)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Switch to the given profile.  Each profile has its own learning state, suspensions and
+    /// log, over the same problems, so that several people can share a database.  The profile is
+    /// not saved in the database, and a newly opened `Store` uses `DEFAULT_PROFILE`.  Returns
+    /// `Error::UnknownProfile` if there is no profile with that name.
+    pub fn set_profile(&mut self, name: &str) -> Result<()> {
+        let count: i64 = self.conn
+            .query_row("SELECT COUNT(*) FROM profiles WHERE name = ?",
+                       &[&name],
+                       |row| row.get(0))?;
+        if count == 0 {
+            return Err(Error::UnknownProfile(name.to_owned()));
+        }
+        self.profile = name.to_owned();
+        self.session_reviews = 0;
+        Ok(())
+    }
+
+    /// Retrieve the current profile.
+    pub fn get_profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Replace the clock this Store uses to determine the current time.  Stores use the
+    /// `SystemClock` by default, this is mainly useful to simulate the passage of time.
+    pub fn set_clock(&mut self, clock: Box<Clock>) {
+        self.clock = clock;
+    }
+
+    /// Get the current time, according to this Store's clock.
+    pub fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
     /// Return a populator that can be used to more rapidly populate the data.  The population will
     /// be done within the context of a single sqlite3 database transaction.
     pub fn populate(&mut self) -> Result<Populator> {
         let tx = self.conn.transaction()?;
-        Ok(Populator { tx: tx })
+        Ok(Populator {
+               tx: tx,
+               profile: self.profile.clone(),
+           })
+    }
+
+    /// Return a `Syncer` that can be used to bring the problems up to date with a complete,
+    /// regenerated, list of problems.  The sync will be done within the context of a single
+    /// sqlite3 database transaction.
+    pub fn sync(&mut self) -> Result<Syncer> {
+        let tx = self.conn.transaction()?;
+        Ok(sync::start(tx))
     }
 
     /// Query for the next problem that has expired.  If Some, then this is the next problem that
     /// should be asked.
-    #[deprecated(since="0.2", note="Please use get_nexts")]
+    #[deprecated(since="0.2.0", note="Please use get_nexts")]
     pub fn get_next(&mut self) -> Result<Option<Problem>> {
-        let mut vec = self.get_nexts(1)?;
+        let mut nexts = self.get_nexts(1)?;
 
         // We can just use pop because there should only be one element.
-        Ok(vec.pop())
+        Ok(nexts.pop())
     }
 
     /// Query for 'n' upcoming problems that have expired.  This will return a Vec of problems,
-    /// with element 0 being the next problem that should be asked.
+    /// with element 0 being the next problem that should be asked.  No more problems will be
+    /// returned than the session's remaining review budget, and new problems are only introduced
+    /// while the day's new problem budget remains.
+    ///
+    /// The budget isn't part of the result.  Call `get_budget` afterwards to find how much of each
+    /// budget is left, such as to tell whether fewer than 'n' problems were returned because a
+    /// limit was reached.
     pub fn get_nexts(&mut self, count: usize) -> Result<Vec<Problem>> {
+        let cur = self.now();
+        let budget = self.get_budget()?;
+        let count = match budget.reviews_left {
+            Some(left) => count.min(left),
+            None => count,
+        };
         let mut rows = {
             let mut stmt = self.conn
-                .prepare("
-                SELECT id, question, answer, next, interval
+                .prepare(&format!("
+                SELECT id, question, answer, next, interval, ease, lapses, phase, step, graduate
                 FROM probs JOIN learning
                 WHERE probs.id = learning.probid
-                    AND next <= ?
+                    AND learning.profile = ?3
+                    AND next <= ?1
+                    {}
                 ORDER BY next
-                LIMIT ?")?;
-            let rows = stmt.query_map(&[&now(), &(count as i64)], |row| {
+                LIMIT ?4",
+                                  AVAILABLE))?;
+            let rows = stmt.query_map(&[&cur, &self.tag, &self.profile, &(count as i64)], |row| {
+                let phase: String = row.get(7);
                 Problem {
                     id: row.get(0),
                     question: row.get(1),
                     answer: row.get(2),
                     next: row.get(3),
                     interval: row.get(4),
+                    ease: row.get(5),
+                    lapses: row.get(6),
+                    phase: steps::decode_phase(&phase, row.get(8)),
+                    graduate: row.get(9),
                 }
             })?;
             let rows: Result<Vec<Problem>> = rows.map(|x| x.map_err(|y| y.into())).collect();
@@ -166,7 +659,7 @@ impl Store {
         // If we got no rows back, fetch a new one.  It doesn't make any sense to return preview
         // results ahead, since they will usually be incorrect (time will pass causing other
         // problems to become ready.
-        if rows.is_empty() {
+        if rows.is_empty() && count > 0 && !budget.new_exhausted() {
             match self.get_new()? {
                 None => (),
                 Some(mut p) => {
@@ -182,20 +675,27 @@ impl Store {
     /// Get a problem that hasn't started being learned.  The interval and "next" will be set
     /// appropriately for a new word.
     pub fn get_new(&mut self) -> Result<Option<Problem>> {
+        let cur = self.now();
         let mut stmt = self.conn
-            .prepare("
+            .prepare(&format!("
             SELECT id, question, answer
             FROM probs
-            WHERE ID NOT IN (SELECT probid FROM learning)
+            WHERE ID NOT IN (SELECT probid FROM learning WHERE profile = ?3)
+                {}
             ORDER BY id
-            LIMIT 1")?;
-        let mut rows = stmt.query_map(&[], |row| {
+            LIMIT 1",
+                              AVAILABLE))?;
+        let mut rows = stmt.query_map(&[&cur, &self.tag, &self.profile], |row| {
                 Problem {
                     id: row.get(0),
                     question: row.get(1),
                     answer: row.get(2),
-                    next: now(),
-                    interval: 5.0,
+                    next: cur,
+                    interval: MIN_INTERVAL,
+                    ease: INITIAL_EASE,
+                    lapses: 0,
+                    phase: Phase::New,
+                    graduate: None,
                 }
             })?;
         match rows.next() {
@@ -205,93 +705,192 @@ impl Store {
     }
 
     /// Update a word, based on a learning factor.  The scale is 1..4, with 1 being totally
-    /// incorrect, and 4 being totally correct.
+    /// incorrect, and 4 being totally correct.  Any other factor results in `Error::InvalidGrade`.
+    ///
+    /// Answering a problem that has already been learned with a 1 counts as a lapse, and the
+    /// problem will be handled as a leech, according to the `LeechPolicy`, if it has lapsed too
+    /// many times.
     pub fn update(&mut self, prob: Problem, factor: u8) -> Result<()> {
-        let orig_factor = factor;
-        let factor = match factor {
-            1 => 0.25,
-            2 => 0.9,
-            3 => 1.2,
-            4 => 2.2,
-            _ => panic!("Invalid factor: {}", factor),
-        };
+        if !(1..=4).contains(&factor) {
+            return Err(Error::InvalidGrade(factor));
+        }
 
         let mut prob = prob;
-
-        let mut rng = thread_rng();
-        let interval = prob.interval;
-        // Compute the interval, capping with a minimum of 5 seconds.
-        prob.interval = (interval * factor * rng.gen_range(0.75, 1.25)).max(5.0);
-        prob.next = now() + prob.interval;
+        let cur = self.now();
+        let lapsed = prob.answer(&*self.scheduler,
+                                 &self.steps,
+                                 &self.rollover,
+                                 cur,
+                                 factor,
+                                 &mut thread_rng());
 
         let tx = self.conn.transaction()?;
-        tx.execute("INSERT OR REPLACE INTO learning VALUES (?, ?, ?)",
-                     &[&prob.id, &prob.next, &prob.interval])?;
-        tx.execute("INSERT INTO log VALUES (?, ?, ?)",
-                   &[&now(), &prob.id, &orig_factor])?;
+        save_learning(&tx, &self.profile, &prob)?;
+        tx.execute("INSERT INTO log (stamp, score, probid, profile) VALUES (?, ?, ?, ?)",
+                   &[&cur, &factor, &prob.id, &self.profile])?;
+        if lapsed && prob.lapses >= self.leech_policy.threshold {
+            tx.execute("INSERT OR IGNORE INTO leeches (profile, probid) VALUES (?, ?)",
+                       &[&self.profile, &prob.id])?;
+            if self.leech_policy.suspend {
+                tx.execute("INSERT OR REPLACE INTO suspended (profile, probid, until)
+                            VALUES (?, ?, NULL)",
+                           &[&self.profile, &prob.id])?;
+            }
+        }
         tx.commit()?;
+        self.session_reviews += 1;
 
         Ok(())
     }
 
-    /// Retrieve statistics about the words available.
+    // The time the current day started at.
+    fn day_start(&self) -> f64 {
+        self.rollover.day_start(self.now())
+    }
+
+    /// Count the problems that will come due in each of the next `count` periods, which are
+    /// calendar hours or days, starting with the current one.  Problems that are suspended, or
+    /// excluded by the tag filter, aren't counted.
+    pub fn get_forecast(&self, granularity: Granularity, count: usize) -> Result<Forecast> {
+        let cur = self.now();
+        let start = match granularity {
+            Granularity::Hours => (cur / granularity.length()).floor() * granularity.length(),
+            Granularity::Days => self.rollover.day_start(cur),
+        };
+        forecast::compute(&self.conn,
+                          &self.tag,
+                          &self.profile,
+                          cur,
+                          start,
+                          granularity,
+                          count)
+    }
+
+    /// Compute statistics about how well problems have been remembered, and the work done, from
+    /// the log.  If there is a tag filter, only problems with that tag are included.
+    pub fn get_stats(&self) -> Result<Stats> {
+        stats::compute(&self.conn,
+                       self.tag.as_deref(),
+                       &self.profile,
+                       &self.buckets,
+                       &|time| self.rollover.day_start(time))
+    }
+
+    /// Retrieve statistics about the words available.  Problems that are suspended or buried are
+    /// only included in the `suspended` and `buried` counts.
     pub fn get_counts(&self) -> Result<Counts> {
+        let cur = self.now();
+
         let unlearned: i64 = self.conn
-            .query_row("
+            .query_row(&format!("
                 SELECT COUNT(*)
                 FROM probs
-                WHERE id NOT IN (SELECT probid FROM learning)",
-                       &[],
+                WHERE id NOT IN (SELECT probid FROM learning WHERE profile = ?3)
+                    {}",
+                                AVAILABLE),
+                       &[&cur, &self.tag, &self.profile],
                        |row| row.get(0))?;
 
-        let cur = now();
-
         let active: i64 = self.conn
-            .query_row("
+            .query_row(&format!("
                 SELECT COUNT(*)
                 FROM probs JOIN learning
                 WHERE probs.id = learning.probid
-                    AND next <= ?",
-                       &[&cur],
+                    AND learning.profile = ?3
+                    AND next <= ?1
+                    {}",
+                                AVAILABLE),
+                       &[&cur, &self.tag, &self.profile],
                        |row| row.get(0))?;
 
         let later: i64 = self.conn
-            .query_row("
+            .query_row(&format!("
                 SELECT COUNT(*)
                 FROM probs JOIN learning
                 WHERE probs.id = learning.probid
-                    and next > ?",
-                       &[&cur],
+                    AND learning.profile = ?3
+                    AND next > ?1
+                    {}",
+                                AVAILABLE),
+                       &[&cur, &self.tag, &self.profile],
+                       |row| row.get(0))?;
+
+        // The problems due by the end of today, and those due tomorrow.
+        let tomorrow = self.day_start() + DAY;
+        let (today, tomorrow): (i64, i64) = self.conn
+            .query_row(&format!("
+                SELECT COUNT(CASE WHEN next < ?4 THEN 1 END),
+                    COUNT(CASE WHEN next >= ?4 AND next < ?5 THEN 1 END)
+                FROM probs JOIN learning
+                WHERE probs.id = learning.probid
+                    AND learning.profile = ?3
+                    {}",
+                                AVAILABLE),
+                       &[&cur, &self.tag, &self.profile, &tomorrow, &(tomorrow + DAY)],
+                       |row| (row.get(0), row.get(1)))?;
+
+        let suspended: i64 = self.conn
+            .query_row("
+                SELECT COUNT(*)
+                FROM suspended
+                WHERE profile = ?2
+                    AND until IS NULL
+                    AND (?1 IS NULL OR probid IN (SELECT probid FROM profile_tags
+                                                  WHERE profile = ?2 AND tag = ?1))",
+                       &[&self.tag, &self.profile],
                        |row| row.get(0))?;
 
-        let mut interval = 1.0;
-        let mut prior = 0.0;
-        let buckets: Vec<_> = COUNT_BUCKETS
+        let buried: i64 = self.conn
+            .query_row("
+                SELECT COUNT(*)
+                FROM suspended
+                WHERE profile = ?3
+                    AND until > ?1
+                    AND (?2 IS NULL OR probid IN (SELECT probid FROM profile_tags
+                                                  WHERE profile = ?3 AND tag = ?2))",
+                       &[&cur, &self.tag, &self.profile],
+                       |row| row.get(0))?;
+
+        let mut buckets: Vec<_> = self.buckets
             .iter()
-            .map(|buk| {
-                interval *= buk.limit;
-                let count: i64 = self.conn
-                    .query_row("
-                    SELECT COUNT(*)
-                    FROM probs JOIN learning
-                    WHERE probs.id = learning.probid
-                        AND interval <= ? AND interval > ?",
-                               &[&interval, &prior],
-                               |row| row.get(0))
-                    .unwrap();
-                prior = interval;
+            .map(|spec| {
                 Bucket {
-                    name: buk.name,
-                    count: count as usize,
+                    name: spec.name.clone(),
+                    count: 0,
+                    stage: spec.stage,
                 }
             })
             .collect();
+        let mut stmt = self.conn
+            .prepare(&format!("
+                SELECT {} AS bucket, COUNT(*)
+                FROM probs JOIN learning
+                WHERE probs.id = learning.probid
+                    AND learning.profile = ?3
+                    {}
+                GROUP BY bucket",
+                              buckets::bucket_index(&self.buckets),
+                              AVAILABLE))?;
+        let rows = stmt.query_map(&[&cur, &self.tag, &self.profile], |row| {
+                let bucket: i64 = row.get(0);
+                let count: i64 = row.get(1);
+                (bucket, count)
+            })?;
+        for row in rows {
+            let (bucket, count) = row?;
+            buckets[bucket as usize].count = count as usize;
+        }
 
         Ok(Counts {
                active: active as usize,
                later: later as usize,
+               due_today: today as usize,
+               due_tomorrow: tomorrow as usize,
                unlearned: unlearned as usize,
+               suspended: suspended as usize,
+               buried: buried as usize,
                buckets: buckets,
+               budget: self.get_budget()?,
            })
     }
 }
@@ -302,48 +901,35 @@ pub struct Counts {
     pub active: usize,
     /// The number of problems that are being learned, but aren't ready to be asked again.
     pub later: usize,
+    /// The number of problems that are due before the end of today, including the active ones.
+    pub due_today: usize,
+    /// The number of problems that will come due tomorrow.
+    pub due_tomorrow: usize,
     /// The number of problems the user has never been shown.
     pub unlearned: usize,
+    /// The number of problems that are suspended.
+    pub suspended: usize,
+    /// The number of problems that are buried until tomorrow.
+    pub buried: usize,
 
     /// Counts of all of the problems, grouped into histogram buckets based on the learning
-    /// interval of the problem.  The bucket names are a short description of the interval.
+    /// interval of the problem, as configured with `set_buckets`.
     pub buckets: Vec<Bucket>,
+
+    /// How much of the limits on new problems and reviews remain.
+    pub budget: Budget,
 }
 
 /// A single histogram bucket describing a number of problems of a given category.
 pub struct Bucket {
     /// A short description of this bucket.
-    pub name: &'static str,
+    pub name: String,
     /// The number of problems in this bucket.
     pub count: usize,
+    /// How far along the problems in this bucket are.
+    pub stage: Stage,
 }
 
-struct BucketBin {
-    name: &'static str,
-    limit: f64,
-}
-
-static COUNT_BUCKETS: &'static [BucketBin] = &[BucketBin {
-     name: "sec",
-     limit: 60.0,
- },
- BucketBin {
-     name: "min",
-     limit: 60.0,
- },
- BucketBin {
-     name: "hr",
-     limit: 24.0,
- },
- BucketBin {
-     name: "day",
-     limit: 30.0,
- },
- BucketBin {
-     name: "mon",
-     limit: 1.0e30,
- }];
-
 /// A single problem retrieved.
 pub struct Problem {
     id: i64,
@@ -354,6 +940,10 @@ pub struct Problem {
     pub answer: String,
     next: f64, // TODO: Make these private, and provide a query.
     interval: f64,
+    ease: f64,
+    lapses: u32,
+    phase: Phase,
+    graduate: Option<f64>,
 }
 
 impl Problem {
@@ -364,8 +954,12 @@ impl Problem {
             id: -1,
             question: question.to_owned(),
             answer: answer.to_owned(),
-            next: now() + 5.0,
-            interval: 5.0,
+            next: now() + MIN_INTERVAL,
+            interval: MIN_INTERVAL,
+            ease: INITIAL_EASE,
+            lapses: 0,
+            phase: Phase::New,
+            graduate: None,
         }
     }
 
@@ -379,6 +973,61 @@ impl Problem {
     pub fn get_interval(&self) -> f64 {
         self.interval
     }
+
+    /// Get the ease of this problem.  This starts at 1.0, and is lowered by poor answers and
+    /// raised by good ones, making the interval grow more slowly or quickly.
+    pub fn get_ease(&self) -> f64 {
+        self.ease
+    }
+
+    /// Get the number of times this problem has been forgotten after it was learned.
+    pub fn get_lapses(&self) -> u32 {
+        self.lapses
+    }
+
+    /// Get where this problem is in the learning process: new, going through its learning or
+    /// relearning steps, or being reviewed.
+    pub fn get_phase(&self) -> Phase {
+        self.phase
+    }
+
+    // Update the learning state of this problem for an answer with the given grade at the time
+    // `cur`.  Returns whether the answer was a lapse.
+    fn answer(&mut self,
+              scheduler: &Scheduler,
+              steps: &Steps,
+              rollover: &Rollover,
+              cur: f64,
+              grade: u8,
+              rng: &mut Rng)
+              -> bool {
+        let elapsed = (cur - (self.next - self.interval)).max(0.0);
+        let prior = Schedule {
+            interval: self.interval,
+            ease: self.ease,
+        };
+        let answer = steps::answer(steps,
+                                   self.phase,
+                                   prior,
+                                   self.graduate,
+                                   grade,
+                                   &mut |prior| scheduler.schedule(prior, elapsed, grade, rng));
+        // Compute the interval, capping with a minimum interval, and moving long ones to the start
+        // of a day.
+        self.next = rollover.due(cur, answer.schedule.interval.max(MIN_INTERVAL));
+        self.interval = self.next - cur;
+        self.ease = answer.schedule.ease;
+
+        // Only forgetting a problem that has been learned counts as a lapse, not forgetting it
+        // while it is still in its steps.
+        let lapsed = grade == 1 && self.phase == Phase::Review;
+        if lapsed {
+            self.lapses += 1;
+        }
+        self.phase = answer.phase;
+        self.graduate = answer.graduate;
+        lapsed
+    }
 }
 
 /// The status of learning.  TODO: This doesn't belong in this crate.
@@ -388,6 +1037,8 @@ pub enum Status {
     Continue(u8),
     /// The user has requested that we stop.
     Stopped,
+    /// The user has asked for this problem to be suspended, rather than answering it.
+    Suspend,
 }
 
 /// A User is something that can be asked to solve a single problem.  It implements `Write` which
@@ -405,6 +1056,7 @@ pub trait User: Write {
 /// A helper to populate a `Store` with `Problem`s.
 pub struct Populator<'a> {
     tx: Transaction<'a>,
+    profile: String,
 }
 
 impl<'a> Populator<'a> {
@@ -416,11 +1068,13 @@ impl<'a> Populator<'a> {
         Ok(())
     }
 
-    /// Add a problem that is in the process of being learned.  The 'next' value is the unix time
-    /// that the question should be asked again, and 'interval' is the current interval.
+    /// Add a problem that is in the process of being learned by the `Store`'s profile.  The
+    /// 'next' value is the unix time that the question should be asked again, and 'interval' is
+    /// the current interval.
     ///
     /// This is used when importing problems from another system, where some of the problems may
-    /// already be learned.
+    /// already be learned.  The imported state is kept, so that replaying the problem's log, as
+    /// `Store::rebuild` does, starts from it.
     pub fn add_learning_problem(&mut self,
                                 question: &str,
                                 answer: &str,
@@ -430,12 +1084,23 @@ impl<'a> Populator<'a> {
         self.tx
             .execute("INSERT INTO probs (question, answer) VALUES (?, ?)",
                      &[&question, &answer])?;
+        let id = self.tx.last_insert_rowid();
         self.tx
-            .execute("INSERT INTO learning VALUES (?, ?, ?)",
-                     &[&self.tx.last_insert_rowid(), &next, &interval])?;
+            .execute("INSERT INTO learning (profile, probid, next, interval) VALUES (?, ?, ?, ?)",
+                     &[&self.profile, &id, &next, &interval])?;
+        self.tx
+            .execute("INSERT INTO imported (profile, probid, next, interval) VALUES (?, ?, ?, ?)",
+                     &[&self.profile, &id, &next, &interval])?;
         Ok(())
     }
 
+    /// Attach tags to the problem with the given question, which must have already been added.
+    /// Tags are short strings that can later be used to restrict which problems are asked with
+    /// `Store::set_tag_filter`.
+    pub fn add_tags(&mut self, question: &str, tags: &[&str]) -> Result<()> {
+        add_tags(&self.tx, question, tags)
+    }
+
     /// Consume the `Populator` and commit.  If the Populator is dropped without calling `commit`,
     /// any changes made by it will be rolled back.
     pub fn commit(self) -> Result<()> {
@@ -444,9 +1109,92 @@ impl<'a> Populator<'a> {
     }
 }
 
+// Write the learning state of a problem for the given profile.
+fn save_learning(conn: &Connection, profile: &str, prob: &Problem) -> Result<()> {
+    let (phase, step) = steps::encode_phase(prob.phase);
+    conn.execute("INSERT OR REPLACE INTO learning
+                      (profile, probid, next, interval, ease, lapses, phase, step, graduate)
+                  VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                 &[&profile,
+                   &prob.id,
+                   &prob.next,
+                   &prob.interval,
+                   &prob.ease,
+                   &prob.lapses,
+                   &phase,
+                   &step,
+                   &prob.graduate])?;
+    Ok(())
+}
+
+// Attach tags to the problem with the given question.
+fn add_tags(conn: &Connection, question: &str, tags: &[&str]) -> Result<()> {
+    let id: i64 = match conn.query_row("SELECT id FROM probs WHERE question = ?",
+                                       &[&question],
+                                       |row| row.get(0)) {
+        Ok(id) => id,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(Error::UnknownProblem(question.to_owned()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    for tag in tags {
+        conn.execute("INSERT OR IGNORE INTO tags (probid, tag) VALUES (?, ?)",
+                     &[&id, tag])?;
+    }
+    Ok(())
+}
+
+// Look up a single value in the config table.
+fn get_config(conn: &Connection, key: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT value FROM config WHERE key = ?")?;
+    let mut rows = stmt.query_map(&[&key], |row| {
+        let value: String = row.get(0);
+        value
+    })?;
+    match rows.next() {
+        Some(text) => Ok(Some(text?)),
+        None => Ok(None),
+    }
+}
+
+// Set a single value in the config table.
+fn set_config(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute("INSERT OR REPLACE INTO config VALUES (?, ?)",
+                 &[&key, &value])?;
+    Ok(())
+}
+
+// Load the scheduler from the config table.  Databases that predate configurable schedulers
+// use the default multiplier scheduler.
+fn get_scheduler_config(conn: &Connection) -> Result<Box<Scheduler>> {
+    match get_config(conn, "scheduler")? {
+        None => Ok(Box::new(Multiplier::default())),
+        Some(name) => {
+            let params = get_config(conn, "scheduler-params")?.unwrap_or_else(String::new);
+            scheduler::from_config(&name, &scheduler::decode_params(&params)?)
+        }
+    }
+}
+
+// Save the scheduler's name and parameters in the config table.
+fn set_scheduler_config(conn: &Connection, sched: &Scheduler) -> Result<()> {
+    set_config(conn, "scheduler", sched.name())?;
+    set_config(conn, "scheduler-params", &scheduler::encode_params(&sched.params()))?;
+    Ok(())
+}
+
+// Switch the database to write-ahead logging, so that read-only Stores can read it while a
+// session is writing to it.  The mode is kept in the database file.
+fn set_wal(conn: &Connection) -> Result<()> {
+    conn.query_row("PRAGMA journal_mode = WAL", &[], |_| ())?;
+    Ok(())
+}
+
 /// Get the current time in the Posix timestamp format.  This is the same time value used by the
 /// 'next' field of the Problems, and can be used, for example, during population to set already
-/// partially-learned problems.
+/// partially-learned problems.  This is always the system time; use `Store::now` to get the time
+/// according to a `Store`'s clock.
 pub fn now() -> f64 {
     let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
     let sec = stamp.as_secs();
@@ -454,3 +1202,17 @@ pub fn now() -> f64 {
 
     sec as f64 + (nsec as f64 / 1.0e9)
 }
+
+// An empty in-memory database with the current schema, for the unit tests of the modules that
+// read and write it.
+#[cfg(test)]
+fn test_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    {
+        let tx = conn.transaction().unwrap();
+        migrate::create_base(&tx).unwrap();
+        migrate::apply(&tx, migrate::BASE_VERSION).unwrap();
+        tx.commit().unwrap();
+    }
+    conn
+}
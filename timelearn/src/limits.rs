@@ -0,0 +1,79 @@
+//! Limits on how much is learned at once.
+//!
+//! Without limits, a long session will keep introducing new problems whenever nothing is due,
+//! which results in a large number of reviews over the following days.  The `Limits` of a `Store`
+//! bound the number of new problems introduced per day, and the number of reviews done in a
+//! single session (the lifetime of the `Store`).  The limits are kept in the `config` table.
+
+use rusqlite::Connection;
+use {get_config, set_config, Error, Result};
+
+/// The configured limits of a `Store`.  A limit of `None` means there is no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The most new problems that will be introduced in a single day.
+    pub new_per_day: Option<usize>,
+    /// The most problems that will be asked in a single session.
+    pub reviews_per_session: Option<usize>,
+}
+
+/// How much of the `Limits` remain.  `None` indicates there is no limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Budget {
+    /// The number of new problems that can still be introduced today.
+    pub new_left: Option<usize>,
+    /// The number of problems that can still be asked in this session.
+    pub reviews_left: Option<usize>,
+}
+
+impl Budget {
+    /// Returns true if no more problems of any kind should be asked in this session.
+    pub fn reviews_exhausted(&self) -> bool {
+        self.reviews_left == Some(0)
+    }
+
+    /// Returns true if no more new problems should be introduced today.
+    pub fn new_exhausted(&self) -> bool {
+        self.new_left == Some(0)
+    }
+}
+
+static NEW_PER_DAY: &'static str = "new-per-day";
+static REVIEWS_PER_SESSION: &'static str = "reviews-per-session";
+
+/// Read the limits from the config table.
+pub fn get_limits(conn: &Connection) -> Result<Limits> {
+    Ok(Limits {
+        new_per_day: get_limit(conn, NEW_PER_DAY)?,
+        reviews_per_session: get_limit(conn, REVIEWS_PER_SESSION)?,
+    })
+}
+
+/// Write the limits to the config table.
+pub fn set_limits(conn: &Connection, limits: &Limits) -> Result<()> {
+    set_limit(conn, NEW_PER_DAY, limits.new_per_day)?;
+    set_limit(conn, REVIEWS_PER_SESSION, limits.reviews_per_session)?;
+    Ok(())
+}
+
+fn get_limit(conn: &Connection, key: &str) -> Result<Option<usize>> {
+    match get_config(conn, key)? {
+        None => Ok(None),
+        Some(text) => {
+            match text.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(Error::InvalidConfig(format!("{}: {:?}", key, text))),
+            }
+        }
+    }
+}
+
+fn set_limit(conn: &Connection, key: &str, value: Option<usize>) -> Result<()> {
+    match value {
+        None => {
+            conn.execute("DELETE FROM config WHERE key = ?", &[&key])?;
+        }
+        Some(value) => set_config(conn, key, &value.to_string())?,
+    }
+    Ok(())
+}
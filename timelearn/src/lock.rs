@@ -0,0 +1,49 @@
+//! Session locks.
+//!
+//! Two sessions writing to the same database at once would each ask the same problems, and
+//! overwrite each other's answers.  To prevent this, a `Store` that can write takes a session
+//! lock, by holding an exclusive transaction open on a small database alongside the main one,
+//! named with a `-session` suffix.  The lock is released when the `Store` is dropped, and SQLite
+//! releases it if the process exits without dropping it, so a crashed session doesn't leave the
+//! database locked.  The lock is advisory: it only keeps out other `Store`s.
+//!
+//! The `-session` file is left in place when the lock is released.  Removing it would let a
+//! session that had already opened it go on to lock a file that no longer exists, while another
+//! session locks a new one.  The file holds no data, so it isn't included in backups, and it can be
+//! deleted whenever no session has the database open.
+
+use rusqlite::{self, Connection, ErrorCode};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use {Error, Result};
+
+/// A held session lock.
+pub struct SessionLock {
+    // The transaction holding the lock is open on this connection.
+    _conn: Connection,
+}
+
+impl SessionLock {
+    /// Take the session lock for the database at `path`, failing with `Error::Locked` if another
+    /// session holds it.
+    pub fn acquire(path: &Path) -> Result<SessionLock> {
+        let conn = Connection::open(lock_name(path))?;
+        // Fail immediately, rather than waiting for the other session to finish.
+        conn.query_row("PRAGMA busy_timeout = 0", &[], |_| ())?;
+        match conn.execute_batch("BEGIN EXCLUSIVE") {
+            Ok(()) => Ok(SessionLock { _conn: conn }),
+            Err(rusqlite::Error::SqliteFailure(ref err, _)) if err.code ==
+                                                                ErrorCode::DatabaseBusy => {
+                Err(Error::Locked(path.display().to_string()))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// The name of the lock database for the database at `path`.
+fn lock_name(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push("-session");
+    PathBuf::from(name)
+}
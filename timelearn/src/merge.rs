@@ -0,0 +1,113 @@
+//! Merging the history of another copy of a database.
+//!
+//! When a database is copied to several machines, each copy collects its own answers.
+//! `Store::merge` brings the answers from another copy, given as a `Dump`, into this one.
+//! Problems are matched by question, and profiles by name, and any that only the other copy has
+//! are added, along with the other copy's tags, leeches, suspensions and imported learning state.
+//! Answers that are in both copies, such as those given before the copy was made, are only kept
+//! once.  The learning state of each problem that gained answers is then recomputed from its merged
+//! log, and the other problems keep the state they have.
+
+use rusqlite::{self, Connection};
+use std::collections::{BTreeSet, HashMap};
+use {replay, sync};
+use {Dump, Error, Result, Rollover, Scheduler, Steps, SCHEMA_VERSION};
+
+/// A summary of the changes made by a merge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The number of problems that were only in the other copy, and were added.
+    pub problems_added: usize,
+    /// The number of answers that were only in the other copy, and were added.
+    pub reviews_added: usize,
+    /// The number of answers that were already in this copy.
+    pub duplicates: usize,
+    /// The number of problems, for each profile, whose learning state was recomputed.
+    pub replayed: usize,
+}
+
+/// Merge the contents of `dump` into the database, replaying with the given scheduler, steps and
+/// rollover.
+pub fn merge(conn: &Connection,
+             scheduler: &Scheduler,
+             steps: &Steps,
+             rollover: &Rollover,
+             dump: &Dump)
+             -> Result<MergeReport> {
+    if dump.schema != SCHEMA_VERSION {
+        return Err(Error::SchemaMismatch(dump.schema.clone()));
+    }
+
+    let mut report = MergeReport::default();
+    for name in &dump.profiles {
+        if conn.execute("INSERT OR IGNORE INTO profiles (name) VALUES (?)", &[name])? > 0 {
+            sync::suspend_retired(conn, name)?;
+        }
+    }
+
+    // The ids of the dump's problems in this database.
+    let mut ids = HashMap::new();
+    for prob in &dump.problems {
+        let id = match conn.query_row("SELECT id FROM probs WHERE question = ?",
+                                      &[&prob.question],
+                                      |row| row.get(0)) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                conn.execute("INSERT INTO probs (question, answer) VALUES (?, ?)",
+                             &[&prob.question, &prob.answer])?;
+                report.problems_added += 1;
+                conn.last_insert_rowid()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        for tag in &prob.tags {
+            conn.execute("INSERT OR IGNORE INTO tags (probid, tag) VALUES (?, ?)",
+                         &[&id, tag])?;
+        }
+        for imp in &prob.imported {
+            conn.execute("INSERT OR IGNORE INTO imported (profile, probid, next, interval)
+                          VALUES (?, ?, ?, ?)",
+                         &[&imp.profile, &id, &imp.next, &imp.interval])?;
+        }
+        for profile in &prob.leeches {
+            conn.execute("INSERT OR IGNORE INTO leeches (profile, probid) VALUES (?, ?)",
+                         &[profile, &id])?;
+        }
+        for susp in &prob.suspended {
+            conn.execute("INSERT OR IGNORE INTO suspended (profile, probid, until)
+                          VALUES (?, ?, ?)",
+                         &[&susp.profile, &id, &susp.until])?;
+        }
+        ids.insert(prob.id, id);
+    }
+
+    // The profiles and problems that gained answers.
+    let mut changed = BTreeSet::new();
+    for ent in &dump.log {
+        let id = match ids.get(&ent.probid) {
+            Some(&id) => id,
+            None => return Err(Error::UnknownProblem(format!("#{}", ent.probid))),
+        };
+        let present: i64 = conn.query_row("
+            SELECT COUNT(*)
+            FROM log
+            WHERE profile = ? AND probid = ? AND stamp = ? AND score = ?",
+                                          &[&ent.profile, &id, &ent.stamp, &ent.score],
+                                          |row| row.get(0))?;
+        if present > 0 {
+            report.duplicates += 1;
+            continue;
+        }
+        conn.execute("INSERT INTO log (stamp, score, probid, profile) VALUES (?, ?, ?, ?)",
+                     &[&ent.stamp, &ent.score, &id, &ent.profile])?;
+        report.reviews_added += 1;
+        changed.insert((ent.profile.clone(), id));
+    }
+
+    for &(ref profile, id) in &changed {
+        replay::rebuild(conn, scheduler, steps, rollover, profile, id)?;
+    }
+    report.replayed = changed.len();
+
+    Ok(report)
+}
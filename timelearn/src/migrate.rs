@@ -0,0 +1,270 @@
+//! Schema migrations.
+//!
+//! The database records its schema version in the `schema_version` table.  Each change to the
+//! schema is a `Migration` from one version to the next, and `upgrade` applies, in order, all of
+//! the migrations needed to bring an older database up to `SCHEMA_VERSION`.  Databases are always
+//! created with the base schema, and then migrated, so the migrations here are the only
+//! description of the current schema.
+//!
+//! To change the schema, add a new entry to the end of `MIGRATIONS`.
+
+use rusqlite::{Connection, Transaction};
+use std::fs;
+use std::path::{Path, PathBuf};
+use {Error, Result};
+
+/// The schema version that new databases start at.
+pub const BASE_VERSION: &'static str = "20170704A";
+
+/// The schema version this version of the crate uses.
+pub const SCHEMA_VERSION: &'static str = "20261017J";
+
+// A single step in the migration chain.
+struct Migration {
+    from: &'static str,
+    to: &'static str,
+    sql: &'static str,
+}
+
+static MIGRATIONS: &'static [Migration] = &[Migration {
+     from: "20170704A",
+     to: "20170709A",
+     sql: "CREATE TABLE log (stamp REAL NOT NULL,
+        score INTEGER NOT NULL,
+        probid INTEGER REFERENCES probs (id) NOT NULL);",
+ },
+ // Prior to this version, the log was written with the score and problem id swapped.  A row
+ // whose probid is a valid score, and whose score isn't, was written this way, so swap those back.
+ // Rows with a probid outside of 1..4 can't have been swapped, and rows with a valid score are
+ // taken to be right, since they can't be told apart from correct answers to problems 1..4.
+ Migration {
+     from: "20170709A",
+     to: "20261017A",
+     sql: "UPDATE log SET score = probid, probid = score
+        WHERE probid BETWEEN 1 AND 4
+            AND score NOT BETWEEN 1 AND 4;",
+ },
+ Migration {
+     from: "20261017A",
+     to: "20261017B",
+     sql: "ALTER TABLE learning ADD COLUMN ease REAL NOT NULL DEFAULT 1.0;",
+ },
+ Migration {
+     from: "20261017B",
+     to: "20261017C",
+     sql: "CREATE TABLE tags (probid INTEGER REFERENCES probs (id) NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (probid, tag));
+        CREATE INDEX tags_tag ON tags (tag);",
+ },
+ // Count lapses.  The lapse count of existing problems is reconstructed from the log: every
+ // incorrect answer after the first answer.  Without learning steps, which came later, those were
+ // all lapses.  With steps, only incorrect answers in review are lapses, so the fails that `stats`
+ // still counts this way can be more than the lapses.
+ Migration {
+     from: "20261017C",
+     to: "20261017D",
+     sql: "ALTER TABLE learning ADD COLUMN lapses INTEGER NOT NULL DEFAULT 0;
+        UPDATE learning SET lapses = (
+            SELECT COUNT(*) FROM log
+            WHERE log.probid = learning.probid
+                AND score = 1
+                AND stamp > (SELECT MIN(stamp) FROM log AS first
+                    WHERE first.probid = learning.probid));",
+ },
+ // Suspended and buried problems.  A problem is suspended if `until` is null, and buried until
+ // then otherwise.
+ Migration {
+     from: "20261017D",
+     to: "20261017E",
+     sql: "CREATE TABLE suspended (probid INTEGER PRIMARY KEY REFERENCES probs (id),
+            until REAL);",
+ },
+ // Learning steps.  Existing problems have all been learned, and are being reviewed.
+ Migration {
+     from: "20261017E",
+     to: "20261017F",
+     sql: "ALTER TABLE learning ADD COLUMN phase TEXT NOT NULL DEFAULT 'review'
+            CHECK (phase IN ('review', 'learning', 'relearning'));
+        ALTER TABLE learning ADD COLUMN step INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE learning ADD COLUMN graduate REAL;",
+ },
+ // Profiles.  The learning state, suspensions and log all belong to a profile, and the existing
+ // ones are given to the default profile.  Sqlite can't change a primary key, so the learning
+ // and suspended tables are rebuilt.
+ Migration {
+     from: "20261017F",
+     to: "20261017G",
+     sql: "CREATE TABLE profiles (name TEXT PRIMARY KEY);
+        INSERT INTO profiles VALUES ('default');
+        CREATE TABLE new_learning (profile TEXT NOT NULL REFERENCES profiles (name),
+            probid INTEGER NOT NULL REFERENCES probs (id),
+            next REAL NOT NULL,
+            interval REAL NOT NULL,
+            ease REAL NOT NULL DEFAULT 1.0,
+            lapses INTEGER NOT NULL DEFAULT 0,
+            phase TEXT NOT NULL DEFAULT 'review'
+                CHECK (phase IN ('review', 'learning', 'relearning')),
+            step INTEGER NOT NULL DEFAULT 0,
+            graduate REAL,
+            PRIMARY KEY (profile, probid));
+        INSERT INTO new_learning
+            SELECT 'default', probid, next, interval, ease, lapses, phase, step, graduate
+            FROM learning;
+        DROP TABLE learning;
+        ALTER TABLE new_learning RENAME TO learning;
+        CREATE INDEX learning_next ON learning (profile, next);
+        CREATE TABLE new_suspended (profile TEXT NOT NULL REFERENCES profiles (name),
+            probid INTEGER NOT NULL REFERENCES probs (id),
+            until REAL,
+            PRIMARY KEY (profile, probid));
+        INSERT INTO new_suspended SELECT 'default', probid, until FROM suspended;
+        DROP TABLE suspended;
+        ALTER TABLE new_suspended RENAME TO suspended;
+        ALTER TABLE log ADD COLUMN profile TEXT NOT NULL DEFAULT 'default'
+            REFERENCES profiles (name);",
+ },
+ // Index the log by problem, so that merging and replaying don't scan the whole log for each
+ // problem.
+ Migration {
+     from: "20261017G",
+     to: "20261017H",
+     sql: "CREATE INDEX log_problem ON log (profile, probid, stamp);",
+ },
+ // The learning state that problems were imported with, which replaying their log starts from.
+ // Problems that have learning state but no log must have been imported.  The imported state of
+ // those that have been answered since wasn't kept, and is lost.
+ Migration {
+     from: "20261017H",
+     to: "20261017I",
+     sql: "CREATE TABLE imported (profile TEXT NOT NULL REFERENCES profiles (name),
+            probid INTEGER NOT NULL REFERENCES probs (id),
+            next REAL NOT NULL,
+            interval REAL NOT NULL,
+            PRIMARY KEY (profile, probid));
+        INSERT INTO imported
+            SELECT profile, probid, next, interval
+            FROM learning
+            WHERE NOT EXISTS (SELECT 1 FROM log
+                              WHERE log.profile = learning.profile
+                                  AND log.probid = learning.probid);",
+ },
+ // Leeches belong to a profile, since lapses do.  The tag filter sees each profile's leeches as
+ // tagged with `LEECH_TAG`, through `profile_tags`.  Leech tags are kept for the profiles whose
+ // lapses reach the threshold.
+ Migration {
+     from: "20261017I",
+     to: "20261017J",
+     sql: "CREATE TABLE leeches (profile TEXT NOT NULL REFERENCES profiles (name),
+            probid INTEGER NOT NULL REFERENCES probs (id),
+            PRIMARY KEY (profile, probid));
+        INSERT INTO leeches
+            SELECT profile, learning.probid
+            FROM learning JOIN tags ON learning.probid = tags.probid
+            WHERE tag = 'leech'
+                AND lapses >= COALESCE((SELECT CAST(value AS INTEGER) FROM config
+                                        WHERE key = 'leech-threshold'), 8);
+        DELETE FROM tags WHERE tag = 'leech';
+        CREATE VIEW profile_tags AS
+            SELECT name AS profile, probid, tag FROM profiles JOIN tags
+            UNION ALL
+            SELECT profile, probid, 'leech' FROM leeches;",
+ }];
+
+/// Create the base schema (at `BASE_VERSION`) within the given transaction.  The caller should
+/// then use `apply` to bring it up to date.
+pub fn create_base(tx: &Transaction) -> Result<()> {
+    tx.execute("CREATE TABLE probs (id INTEGER PRIMARY KEY,
+        question TEXT UNIQUE,
+        answer TEXT NOT NULL)",
+               &[])?;
+    tx.execute("CREATE TABLE learning (probid INTEGER PRIMARY KEY REFERENCES probs (id),
+        next REAL NOT NULL,
+        interval REAL NOT NULL)",
+               &[])?;
+    tx.execute("CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT NOT NULL)", &[])?;
+    tx.execute("CREATE INDEX learning_next ON learning (next)", &[])?;
+    tx.execute("CREATE TABLE schema_version (version TEXT NOT NULL)", &[])?;
+    tx.execute("INSERT INTO schema_version VALUES (?)", &[&BASE_VERSION])?;
+    Ok(())
+}
+
+/// Apply all of the migrations needed to get from the version `from` to `SCHEMA_VERSION`, within
+/// the given transaction.
+pub fn apply(tx: &Transaction, from: &str) -> Result<()> {
+    let mut version = from.to_owned();
+    for mig in steps(from)? {
+        tx.execute_batch(mig.sql)?;
+        tx.execute("UPDATE schema_version SET version = ? WHERE version = ?",
+                   &[&mig.to, &version])?;
+        version = mig.to.to_owned();
+    }
+    Ok(())
+}
+
+/// Bring the database at `path`, opened as `conn`, up to the current schema version.  If any
+/// migrations are needed, a copy of the database file is made first, and the migrations are all
+/// applied in a single transaction.
+pub fn upgrade(conn: &mut Connection, path: &Path) -> Result<()> {
+    let version = get_version(conn)?;
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Make sure we know how to upgrade before making the backup.
+    steps(&version)?;
+    // Bring any changes from the write-ahead log into the file, so that the copy has them.
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", &[], |_| ())?;
+    fs::copy(path, backup_name(path, &version))?;
+
+    let tx = conn.transaction()?;
+    apply(&tx, &version)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Read the schema version from the database.
+pub fn get_version(conn: &Connection) -> Result<String> {
+    let mut stmt = conn.prepare("SELECT version FROM schema_version")?;
+    let mut rows = stmt.query_map(&[], |row| {
+            let vers: String = row.get(0);
+            vers
+        })?;
+    let version = match rows.next() {
+        Some(text) => text?,
+        None => return Err(Error::BadSchema("no schema version present")),
+    };
+    match rows.next() {
+        Some(_) => Err(Error::BadSchema("multiple rows in schema_version")),
+        None => Ok(version),
+    }
+}
+
+// Find the migrations needed to get from the given version to the current version.
+fn steps(from: &str) -> Result<&'static [Migration]> {
+    if from == SCHEMA_VERSION {
+        return Ok(&[]);
+    }
+    match MIGRATIONS.iter().position(|m| m.from == from) {
+        Some(pos) => Ok(&MIGRATIONS[pos..]),
+        None => Err(Error::SchemaMismatch(from.to_owned())),
+    }
+}
+
+// Choose a name for the backup of a database, one that doesn't already exist.
+fn backup_name(path: &Path, version: &str) -> PathBuf {
+    let mut num = 0;
+    loop {
+        let mut name = path.as_os_str().to_owned();
+        if num == 0 {
+            name.push(format!(".{}.bak", version));
+        } else {
+            name.push(format!(".{}.{}.bak", version, num));
+        }
+        let name = PathBuf::from(name);
+        if !name.exists() {
+            return name;
+        }
+        num += 1;
+    }
+}
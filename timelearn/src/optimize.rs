@@ -0,0 +1,300 @@
+//! Fitting scheduler parameters to the log.
+//!
+//! Each answer in the log to a problem that is being reviewed records whether it was remembered
+//! after a known time.  Replaying the log through a scheduler gives the interval the problem would
+//! have had at each of these answers, and the scheduler's `recall` the probability that it would
+//! have been remembered.  `optimize` searches for the parameters that make the recorded answers
+//! most likely, by scaling each of the scheduler's `tunable` parameters up and down in turn, with
+//! smaller and smaller steps until none of them improves the fit.  Each parameter is kept within
+//! a factor of `MAX_CHANGE` of where it started, so that a short or one-sided history can't run
+//! away with it.  The fuzz is left out while fitting, so that the search is deterministic.
+
+use rand::Rng;
+use rusqlite::Connection;
+use replay;
+use scheduler::from_config;
+use {Phase, Result, Rollover, Schedule, Scheduler, Steps, DAY};
+
+/// The result of fitting a scheduler's parameters to the log, from `Store::optimize`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fit {
+    /// The name of the scheduler.
+    pub name: String,
+    /// The scheduler's current parameters.
+    pub old_params: Vec<f64>,
+    /// The fitted parameters.
+    pub params: Vec<f64>,
+    /// The number of answers the parameters were fitted to.
+    pub reviews: usize,
+    /// The mean negative log likelihood of those answers with the current parameters.
+    pub old_loss: f64,
+    /// The mean negative log likelihood of those answers with the fitted parameters.  Lower is
+    /// better.
+    pub loss: f64,
+    /// The expected number of reviews with the fitted parameters, relative to the current ones.
+    pub workload: f64,
+}
+
+/// The furthest a parameter may be moved from its current value, as a factor.
+pub const MAX_CHANGE: f64 = 4.0;
+
+// The most rounds of adjustments the search will make.
+const MAX_ROUNDS: usize = 200;
+
+// Probabilities are kept this far from 0 and 1, so that a single surprising answer has a finite
+// cost.
+const EPSILON: f64 = 1.0e-6;
+
+/// Fit the parameters of `scheduler` to the log of every profile, replaying it with `steps` and
+/// `rollover`.
+pub fn optimize(conn: &Connection,
+                scheduler: &Scheduler,
+                steps: &Steps,
+                rollover: &Rollover)
+                -> Result<Fit> {
+    let histories = histories(conn)?;
+    let name = scheduler.name();
+    let old_params = scheduler.params();
+    let old = evaluate(scheduler, steps, rollover, &histories);
+
+    let mut params = old_params.clone();
+    let mut best = old.clone();
+    let mut step = 0.5;
+    let mut rounds = 0;
+    while step > 0.001 && rounds < MAX_ROUNDS {
+        rounds += 1;
+        let mut improved = false;
+        for &i in &scheduler.tunable() {
+            for &factor in &[1.0 + step, 1.0 / (1.0 + step)] {
+                let mut trial = params.clone();
+                trial[i] *= factor;
+                let ratio = trial[i] / old_params[i];
+                if !(1.0 / MAX_CHANGE..=MAX_CHANGE).contains(&ratio) {
+                    continue;
+                }
+                let result = evaluate(&*from_config(name, &trial)?, steps, rollover, &histories);
+                if result.loss < best.loss {
+                    params = trial;
+                    best = result;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+
+    Ok(Fit {
+           name: name.to_owned(),
+           old_params: old_params,
+           params: params,
+           reviews: old.reviews,
+           old_loss: old.mean_loss(),
+           loss: best.mean_loss(),
+           workload: if old.load > 0.0 { best.load / old.load } else { 1.0 },
+       })
+}
+
+// The answers to one problem, as its id and the (stamp, score) of each answer.
+type History = (i64, Vec<(f64, u8)>);
+
+// The answers in the log, for each profile and problem, in the order they were given.
+fn histories(conn: &Connection) -> Result<Vec<History>> {
+    let mut stmt = conn.prepare("
+        SELECT profile, probid, stamp, score
+        FROM log
+        WHERE score BETWEEN 1 AND 4
+        ORDER BY profile, probid, stamp")?;
+    let rows = stmt.query_map(&[], |row| {
+            let profile: String = row.get(0);
+            let probid: i64 = row.get(1);
+            let stamp: f64 = row.get(2);
+            let score: u8 = row.get(3);
+            (profile, probid, stamp, score)
+        })?;
+
+    let mut result: Vec<History> = vec![];
+    let mut last: Option<(String, i64)> = None;
+    for row in rows {
+        let (profile, probid, stamp, score) = row?;
+        let key = Some((profile, probid));
+        if key != last {
+            result.push((probid, vec![]));
+            last = key;
+        }
+        result.last_mut().unwrap().1.push((stamp, score));
+    }
+    Ok(result)
+}
+
+// How well a scheduler predicts the answers in the log, and how often it would ask the problems.
+#[derive(Clone)]
+struct Evaluation {
+    // The total negative log likelihood of the answers.
+    loss: f64,
+    // The number of answers predicted.
+    reviews: usize,
+    // The number of times per day the problems would be asked, at their final intervals.
+    load: f64,
+}
+
+impl Evaluation {
+    fn mean_loss(&self) -> f64 {
+        if self.reviews > 0 {
+            self.loss / self.reviews as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+fn evaluate(scheduler: &Scheduler,
+            steps: &Steps,
+            rollover: &Rollover,
+            histories: &[History])
+            -> Evaluation {
+    let mut result = Evaluation {
+        loss: 0.0,
+        reviews: 0,
+        load: 0.0,
+    };
+    for &(probid, ref answers) in histories {
+        let mut prob = replay::unanswered(probid, answers[0].0);
+        for &(stamp, score) in answers {
+            if prob.phase == Phase::Review {
+                let prior = Schedule {
+                    interval: prob.interval,
+                    ease: prob.ease,
+                };
+                let elapsed = (stamp - (prob.next - prob.interval)).max(0.0);
+                let recall = scheduler.recall(prior, elapsed).clamp(EPSILON, 1.0 - EPSILON);
+                let likelihood = if score > 1 { recall } else { 1.0 - recall };
+                result.loss -= likelihood.ln();
+                result.reviews += 1;
+            }
+            prob.answer(scheduler, steps, rollover, stamp, score, &mut NoFuzz);
+        }
+        result.load += DAY / prob.interval;
+    }
+    result
+}
+
+// A random number generator that always gives the middle of its range, where the fuzz leaves
+// intervals unchanged.  The floating point values are given directly, as the default ones are
+// built from the low bits of the integers, and would be near 0.
+struct NoFuzz;
+
+impl Rng for NoFuzz {
+    fn next_u32(&mut self) -> u32 {
+        1 << 31
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        1 << 63
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        0.5
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        0.5
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {test_db, Multiplier, MIN_INTERVAL};
+
+    // A database holding the given answers, each a profile, a problem, a time and a grade.
+    fn log(answers: &[(&str, i64, f64, u8)]) -> Connection {
+        let conn = test_db();
+        for &(profile, probid, stamp, score) in answers {
+            conn.execute("INSERT INTO log (stamp, score, probid, profile) VALUES (?, ?, ?, ?)",
+                         &[&stamp, &(score as i64), &probid, &profile])
+                .unwrap();
+        }
+        conn
+    }
+
+    // A problem answered 100 days apart, and remembered each time.
+    fn remembered(probid: i64) -> Vec<(&'static str, i64, f64, u8)> {
+        (0..5).map(|i| ("default", probid, i as f64 * 100.0 * DAY, 3)).collect()
+    }
+
+    #[test]
+    fn grouping() {
+        let conn = log(&[("default", 2, 30.0, 3),
+                         ("default", 1, 20.0, 3),
+                         ("other", 1, 10.0, 2),
+                         ("default", 1, 10.0, 1),
+                         ("default", 1, 15.0, 0),
+                         ("default", 2, 40.0, 5)]);
+        assert_eq!(histories(&conn).unwrap(),
+                   vec![(1, vec![(10.0, 1), (20.0, 3)]),
+                        (2, vec![(30.0, 3)]),
+                        (1, vec![(10.0, 2)])]);
+    }
+
+    #[test]
+    fn no_fuzz() {
+        let prior = Schedule {
+            interval: DAY,
+            ease: 1.0,
+        };
+        let fuzzy = Multiplier::default();
+        let exact = Multiplier { fuzz: 0.0, ..Multiplier::default() };
+        let fuzzy = fuzzy.schedule(prior, DAY, 3, &mut NoFuzz);
+        let exact = exact.schedule(prior, DAY, 3, &mut NoFuzz);
+        assert_eq!(fuzzy, exact);
+    }
+
+    #[test]
+    fn evaluation() {
+        let scheduler = Multiplier::default();
+        let steps = Steps::default();
+        let rollover = Rollover::default();
+
+        let empty = evaluate(&scheduler, &steps, &rollover, &[]);
+        assert_eq!(empty.reviews, 0);
+        assert_eq!(empty.load, 0.0);
+        assert_eq!(empty.mean_loss(), 0.0);
+
+        // The first answer, to a new problem, predicts nothing.
+        let once = evaluate(&scheduler, &steps, &rollover, &[(1, vec![(0.0, 3)])]);
+        assert_eq!(once.reviews, 0);
+        assert_eq!(once.load, DAY / (MIN_INTERVAL * 1.2));
+
+        // Forgetting before the problem is due is more surprising than remembering.
+        let good = evaluate(&scheduler, &steps, &rollover, &[(1, vec![(0.0, 3), (3.0, 3)])]);
+        let bad = evaluate(&scheduler, &steps, &rollover, &[(1, vec![(0.0, 3), (3.0, 1)])]);
+        assert_eq!(good.reviews, 1);
+        assert_eq!(bad.reviews, 1);
+        assert!(good.loss > 0.0);
+        assert!(good.loss < bad.loss);
+    }
+
+    // Problems that are always remembered, long after they were due, call for longer intervals,
+    // but the fit shouldn't move the parameters further than `MAX_CHANGE`.
+    #[test]
+    fn bounds() {
+        let mut answers = remembered(1);
+        answers.extend(remembered(2));
+        let conn = log(&answers);
+        let scheduler = Multiplier::default();
+        let fit = optimize(&conn, &scheduler, &Steps::default(), &Rollover::default()).unwrap();
+
+        assert_eq!(fit.reviews, 8);
+        assert!(fit.loss < fit.old_loss);
+        assert!(fit.params[2] > 2.0 * fit.old_params[2]);
+        for (&param, &old) in fit.params.iter().zip(&fit.old_params) {
+            assert!(param <= old * MAX_CHANGE);
+            assert!(param >= old / MAX_CHANGE);
+        }
+        assert_eq!(fit.params[4], fit.old_params[4]);
+        assert!(fit.workload < 1.0);
+    }
+}
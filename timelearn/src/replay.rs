@@ -0,0 +1,206 @@
+//! Recomputing learning state from the log.
+//!
+//! The log holds every answer given, so the learning state of a problem can be reconstructed by
+//! answering it again, in order, with the current scheduler, steps and rollover.  The scheduler's
+//! random fuzz is seeded from the problem's id, so replaying the same log always gives the same
+//! state, although not necessarily the state that the answers originally produced.
+//!
+//! Problems that were imported with learning state, by `Populator::add_learning_problem`, have no
+//! log of how they reached it.  Their replay starts from the imported state, and only the answers
+//! given after it are replayed.
+
+use rand::{SeedableRng, XorShiftRng};
+use rusqlite::{self, Connection};
+use {save_learning, Phase, Problem, Result, Rollover, Scheduler, Steps, INITIAL_EASE,
+     MIN_INTERVAL};
+
+/// Recompute the learning state of the problem `probid` for `profile` from its log.  Returns
+/// `None` if the profile has never answered the problem, or hasn't answered it since it was
+/// imported.  Only the id and learning state of the result are filled in.
+pub fn replay(conn: &Connection,
+              scheduler: &Scheduler,
+              steps: &Steps,
+              rollover: &Rollover,
+              profile: &str,
+              probid: i64)
+              -> Result<Option<Problem>> {
+    let mut start = match conn.query_row("SELECT next, interval FROM imported
+                                          WHERE profile = ? AND probid = ?",
+                                         &[&profile, &probid],
+                                         |row| imported(probid, row.get(0), row.get(1))) {
+        Ok(prob) => Some(prob),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e.into()),
+    };
+    // The imported state was reached when the problem was last answered, an interval before it
+    // is due.  Any log from before then is already part of it.
+    let since = start.as_ref().map(|prob| prob.next - prob.interval);
+
+    let mut stmt = conn.prepare("
+        SELECT stamp, score
+        FROM log
+        WHERE profile = ? AND probid = ?
+        ORDER BY stamp")?;
+    let rows = stmt.query_map(&[&profile, &probid], |row| {
+            let stamp: f64 = row.get(0);
+            let score: u8 = row.get(1);
+            (stamp, score)
+        })?;
+
+    let mut rng = XorShiftRng::from_seed([probid as u32, 1, 2, 3]);
+    let mut result: Option<Problem> = None;
+    for row in rows {
+        let (stamp, score) = row?;
+        if !(1..=4).contains(&score) || since.is_some_and(|since| stamp <= since) {
+            continue;
+        }
+        if result.is_none() {
+            result = start.take();
+        }
+        let prob = result.get_or_insert_with(|| unanswered(probid, stamp));
+        prob.answer(scheduler, steps, rollover, stamp, score, &mut rng);
+    }
+    Ok(result)
+}
+
+/// The state of the problem `probid` when it is first answered at `stamp`.  The problem is new,
+/// as from `Store::get_new`.
+pub fn unanswered(probid: i64, stamp: f64) -> Problem {
+    Problem {
+        id: probid,
+        question: String::new(),
+        answer: String::new(),
+        next: stamp,
+        interval: MIN_INTERVAL,
+        ease: INITIAL_EASE,
+        lapses: 0,
+        phase: Phase::New,
+        graduate: None,
+    }
+}
+
+// The state of the problem `probid` when it was imported, due at `next` with the given `interval`,
+// as from `Populator::add_learning_problem`.
+fn imported(probid: i64, next: f64, interval: f64) -> Problem {
+    Problem {
+        id: probid,
+        question: String::new(),
+        answer: String::new(),
+        next: next,
+        interval: interval,
+        ease: INITIAL_EASE,
+        lapses: 0,
+        phase: Phase::Review,
+        graduate: None,
+    }
+}
+
+/// Replace the learning state of the problem `probid` for `profile` with the state replayed from
+/// its log.  Problems that the profile has never answered, or hasn't answered since they were
+/// imported, are left as they are.  Returns the
+/// replayed state, if there is one.
+pub fn rebuild(conn: &Connection,
+               scheduler: &Scheduler,
+               steps: &Steps,
+               rollover: &Rollover,
+               profile: &str,
+               probid: i64)
+               -> Result<Option<Problem>> {
+    let prob = replay(conn, scheduler, steps, rollover, profile, probid)?;
+    if let Some(ref prob) = prob {
+        save_learning(conn, profile, prob)?;
+    }
+    Ok(prob)
+}
+
+/// The result of replaying the log of a single problem, from `Store::rebuild`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rebuilt {
+    /// The question of the problem.
+    pub question: String,
+    /// When the problem was due, and its interval, before the replay, or `None` if it had no
+    /// learning state.
+    pub stored: Option<(f64, f64)>,
+    /// When the problem is due, and its interval, according to the replay.
+    pub replayed: (f64, f64),
+}
+
+impl Rebuilt {
+    /// Whether the replayed state differs from the stored state.  Times within a second of each
+    /// other are considered the same.
+    pub fn changed(&self) -> bool {
+        match self.stored {
+            None => true,
+            Some((next, interval)) => {
+                (next - self.replayed.0).abs() > 1.0 || (interval - self.replayed.1).abs() > 1.0
+            }
+        }
+    }
+}
+
+/// Replay the log of every problem that `profile` has answered, and, if `write` is set, replace
+/// their learning state.  Problems that haven't been answered since they were imported are left
+/// out.
+pub fn rebuild_all(conn: &Connection,
+                   scheduler: &Scheduler,
+                   steps: &Steps,
+                   rollover: &Rollover,
+                   profile: &str,
+                   write: bool)
+                   -> Result<Vec<Rebuilt>> {
+    let mut stmt = conn.prepare("
+        SELECT probs.id, question, next, interval
+        FROM probs LEFT JOIN learning ON probs.id = learning.probid AND learning.profile = ?1
+        WHERE probs.id IN (SELECT probid FROM log WHERE profile = ?1)
+        ORDER BY probs.id")?;
+    let rows = stmt.query_map(&[&profile], |row| {
+            let id: i64 = row.get(0);
+            let question: String = row.get(1);
+            let next: Option<f64> = row.get(2);
+            let interval: Option<f64> = row.get(3);
+            (id, question, next.and_then(|next| interval.map(|interval| (next, interval))))
+        })?;
+    let mut stored = vec![];
+    for row in rows {
+        stored.push(row?);
+    }
+
+    let mut result = vec![];
+    for (id, question, old) in stored {
+        let prob = match replay(conn, scheduler, steps, rollover, profile, id)? {
+            Some(prob) => prob,
+            None => continue,
+        };
+        if write {
+            save_learning(conn, profile, &prob)?;
+        }
+        result.push(Rebuilt {
+                        question: question,
+                        stored: old,
+                        replayed: (prob.next, prob.interval),
+                    });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rebuilt(stored: Option<(f64, f64)>) -> Rebuilt {
+        Rebuilt {
+            question: "q".to_owned(),
+            stored: stored,
+            replayed: (1000.0, 60.0),
+        }
+    }
+
+    #[test]
+    fn changed() {
+        assert!(rebuilt(None).changed());
+        assert!(!rebuilt(Some((1000.0, 60.0))).changed());
+        assert!(!rebuilt(Some((1000.5, 59.5))).changed());
+        assert!(rebuilt(Some((1002.0, 60.0))).changed());
+        assert!(rebuilt(Some((1000.0, 62.0))).changed());
+    }
+}
@@ -0,0 +1,164 @@
+//! Calendar days.
+//!
+//! Days are counted in the user's local time, and start at a configurable hour, so that a session
+//! late at night still counts as part of the day before.  Problems scheduled a day or more ahead
+//! come due at the start of a day, so that they are all ready together, rather than trickling in
+//! at whatever time of day they were last answered.  The day-start hour and the offset of local
+//! time from UTC are kept in the `config` table.  By default, days start at midnight UTC.
+//!
+//! The offset is fixed, and doesn't follow daylight saving time.  Where the clocks change, days
+//! start an hour off from the configured hour for part of the year, unless the offset is updated
+//! when the clocks change.
+
+use rusqlite::Connection;
+use std::str::FromStr;
+use {get_config, set_config, Error, Result, DAY};
+
+/// When days start.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rollover {
+    /// The local hour, from 0 to 23, at which each day starts.
+    pub hour: u32,
+    /// The offset of local time from UTC, in minutes, positive east of Greenwich.  This is used
+    /// for all times, whatever the offset of the local timezone was at that time.
+    pub utc_offset: i32,
+}
+
+impl Rollover {
+    /// The time at which the day holding `time` started.
+    pub fn day_start(&self, time: f64) -> f64 {
+        // The number of seconds after midnight UTC that days start.
+        let shift = self.hour as f64 * 3600.0 - self.utc_offset as f64 * 60.0;
+        ((time - shift) / DAY).floor() * DAY + shift
+    }
+
+    /// The time at which a problem answered at `cur` with the given interval comes due.
+    /// Intervals of a day or more are rounded to the nearest start of a day, which is always a
+    /// later day than `cur`.
+    pub fn due(&self, cur: f64, interval: f64) -> f64 {
+        if interval < DAY {
+            cur + interval
+        } else {
+            self.day_start(cur + interval + DAY / 2.0)
+        }
+    }
+}
+
+static DAY_START_HOUR: &'static str = "day-start-hour";
+static UTC_OFFSET: &'static str = "utc-offset";
+
+/// Read the rollover from the config table.
+pub fn get_rollover(conn: &Connection) -> Result<Rollover> {
+    let rollover = Rollover {
+        hour: get_number(conn, DAY_START_HOUR)?.unwrap_or(0),
+        utc_offset: get_number(conn, UTC_OFFSET)?.unwrap_or(0),
+    };
+    check_rollover(&rollover)?;
+    Ok(rollover)
+}
+
+/// Write the rollover to the config table.
+pub fn set_rollover(conn: &Connection, rollover: &Rollover) -> Result<()> {
+    check_rollover(rollover)?;
+    set_config(conn, DAY_START_HOUR, &rollover.hour.to_string())?;
+    set_config(conn, UTC_OFFSET, &rollover.utc_offset.to_string())?;
+    Ok(())
+}
+
+fn get_number<T: FromStr>(conn: &Connection, key: &str) -> Result<Option<T>> {
+    match get_config(conn, key)? {
+        None => Ok(None),
+        Some(text) => {
+            match text.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(Error::InvalidConfig(format!("{}: {:?}", key, text))),
+            }
+        }
+    }
+}
+
+// Make sure the hour is within a day, and the offset is one that a timezone could have.
+fn check_rollover(rollover: &Rollover) -> Result<()> {
+    if rollover.hour >= 24 {
+        return Err(Error::InvalidConfig(format!("{}: {}", DAY_START_HOUR, rollover.hour)));
+    }
+    if rollover.utc_offset.abs() > 14 * 60 {
+        return Err(Error::InvalidConfig(format!("{}: {}", UTC_OFFSET, rollover.utc_offset)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_db;
+
+    // 2001-09-09 08:00 UTC, which is 01:00 at UTC-7.
+    const CUR: f64 = 1000022400.0;
+
+    // Days that start at 04:00, at UTC-7, which is 11:00 UTC.
+    const LOCAL: Rollover = Rollover {
+        hour: 4,
+        utc_offset: -7 * 60,
+    };
+
+    #[test]
+    fn day_start() {
+        let utc = Rollover::default();
+        assert_eq!(utc.day_start(CUR), CUR - 8.0 * 3600.0);
+        assert_eq!(utc.day_start(CUR - 8.0 * 3600.0), CUR - 8.0 * 3600.0);
+
+        // At 01:00 local time, the day started at 04:00 the day before.
+        let start = CUR - 21.0 * 3600.0;
+        assert_eq!(LOCAL.day_start(CUR), start);
+        assert_eq!(LOCAL.day_start(start), start);
+        assert_eq!(LOCAL.day_start(start - 1.0), start - DAY);
+        assert_eq!(LOCAL.day_start(start + DAY - 1.0), start);
+
+        // Midnight at UTC+10 is 14:00 UTC the day before.
+        let east = Rollover {
+            hour: 0,
+            utc_offset: 10 * 60,
+        };
+        assert_eq!(east.day_start(0.0), -10.0 * 3600.0);
+        assert_eq!(east.day_start(14.0 * 3600.0), 14.0 * 3600.0);
+    }
+
+    #[test]
+    fn due() {
+        let start = LOCAL.day_start(CUR);
+        assert_eq!(LOCAL.due(CUR, 60.0), CUR + 60.0);
+        assert_eq!(LOCAL.due(CUR, DAY - 1.0), CUR + DAY - 1.0);
+        assert_eq!(LOCAL.due(CUR, DAY), start + 2.0 * DAY);
+        assert_eq!(LOCAL.due(CUR, 1.4 * DAY), start + 2.0 * DAY);
+        assert_eq!(LOCAL.due(CUR, 2.2 * DAY), start + 3.0 * DAY);
+
+        // Answered just after the day started, a day's interval is the next day.
+        assert_eq!(LOCAL.due(start + 60.0, DAY), start + DAY);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let conn = test_db();
+        assert_eq!(get_rollover(&conn).unwrap(), Rollover::default());
+
+        set_rollover(&conn, &LOCAL).unwrap();
+        assert_eq!(get_config(&conn, DAY_START_HOUR).unwrap(), Some("4".to_owned()));
+        assert_eq!(get_config(&conn, UTC_OFFSET).unwrap(), Some("-420".to_owned()));
+        assert_eq!(get_rollover(&conn).unwrap(), LOCAL);
+    }
+
+    #[test]
+    fn invalid() {
+        let conn = test_db();
+        assert!(set_rollover(&conn, &Rollover { hour: 24, ..LOCAL }).is_err());
+        assert!(set_rollover(&conn, &Rollover { utc_offset: 15 * 60, ..LOCAL }).is_err());
+        assert!(set_rollover(&conn, &Rollover { utc_offset: -14 * 60, ..LOCAL }).is_ok());
+        assert_eq!(get_rollover(&conn).unwrap().utc_offset, -14 * 60);
+
+        set_config(&conn, DAY_START_HOUR, "25").unwrap();
+        assert!(get_rollover(&conn).is_err());
+        set_config(&conn, DAY_START_HOUR, "noon").unwrap();
+        assert!(get_rollover(&conn).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+//! Scheduling algorithms.
+//!
+//! A `Scheduler` decides, given the current interval of a problem and how well the user answered
+//! it, what the next interval should be.  The `Store` keeps track of which scheduler a given
+//! database uses (and its parameters) in the `config` table, so that each database can use its
+//! own algorithm.
+//!
+//! Each problem also has an ease, a multiplier that adjusts how quickly its interval grows.
+//! Problems start with an ease of 1.0, and the schedulers lower it as a problem is answered
+//! poorly, and raise it as it is answered well, so that hard problems come back more often than
+//! easy ones.
+
+use rand::Rng;
+use {Error, Result, DAY};
+
+/// The scheduling state of a single problem.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Schedule {
+    /// The interval, in seconds, until the problem should be asked again.
+    pub interval: f64,
+    /// The ease of the problem, a relative measure of how quickly the interval grows.
+    pub ease: f64,
+}
+
+/// The ease given to problems that haven't been learned yet.
+pub const INITIAL_EASE: f64 = 1.0;
+
+/// The smallest ease a problem can have.
+pub const MIN_EASE: f64 = 0.5;
+
+/// The largest ease a problem can have.
+pub const MAX_EASE: f64 = 2.5;
+
+/// A scheduling algorithm.
+pub trait Scheduler {
+    /// The name of this scheduler.  This is the name stored in the database, and given to
+    /// `from_config` to reconstruct the scheduler.
+    fn name(&self) -> &'static str;
+
+    /// The parameters of this scheduler, in the order expected by `from_config`.
+    fn params(&self) -> Vec<f64>;
+
+    /// Compute the next schedule for a problem.  `prior` is the schedule the problem was last
+    /// given, `elapsed` is the time (in seconds) since the problem was last asked, and `grade` is
+    /// the 1..4 rating of how well the user answered.  The `Store` will enforce a minimum
+    /// interval on the result.
+    fn schedule(&self, prior: Schedule, elapsed: f64, grade: u8, rng: &mut Rng) -> Schedule;
+
+    /// The probability that a problem with the schedule `prior` is remembered `elapsed` seconds
+    /// after it was last asked.  By default, recall follows the FSRS forgetting curve, falling to
+    /// 90% at the end of the interval.
+    fn recall(&self, prior: Schedule, elapsed: f64) -> f64 {
+        forgetting_curve(0.9, prior.interval, elapsed)
+    }
+
+    /// The indices, into `params`, of the parameters that `Store::optimize` may fit to the log.
+    /// Parameters that don't describe how problems are forgotten, such as the fuzz, are left out.
+    fn tunable(&self) -> Vec<usize> {
+        vec![]
+    }
+}
+
+/// The probability of recalling a problem `elapsed` seconds after it was last asked, when recall
+/// falls to `retention` after `interval` seconds.  This is the power curve used by FSRS.
+pub fn forgetting_curve(retention: f64, interval: f64, elapsed: f64) -> f64 {
+    1.0 / (1.0 + elapsed / interval * (1.0 / retention - 1.0))
+}
+
+/// Adjust an ease after answering with the given grade.  This is the SM-2 ease formula, scaled so
+/// that an ease of 1.0 corresponds to the SM-2 initial ease factor of 2.5.
+pub fn adjust_ease(ease: f64, grade: u8) -> f64 {
+    let sm2 = Sm2::default();
+    (sm2.adjust_ease(ease * sm2.ease, grade) / sm2.ease).clamp(MIN_EASE, MAX_EASE)
+}
+
+/// Reconstruct a scheduler from its name and parameters, as stored in the database.
+pub fn from_config(name: &str, params: &[f64]) -> Result<Box<Scheduler>> {
+    match name {
+        "multiplier" => Ok(Box::new(Multiplier::from_params(params)?)),
+        "sm2" => Ok(Box::new(Sm2::from_params(params)?)),
+        "fsrs" => Ok(Box::new(Fsrs::from_params(params)?)),
+        _ => Err(Error::InvalidConfig(format!("Unknown scheduler: {:?}", name))),
+    }
+}
+
+/// Encode scheduler parameters as text for the config table.
+pub fn encode_params(params: &[f64]) -> String {
+    let text: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+    text.join(",")
+}
+
+/// Decode scheduler parameters, as encoded by `encode_params`.
+pub fn decode_params(text: &str) -> Result<Vec<f64>> {
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut result = vec![];
+    for field in text.split(',') {
+        match field.trim().parse() {
+            Ok(value) => result.push(value),
+            Err(_) => {
+                return Err(Error::InvalidConfig(format!("Invalid scheduler parameter: {:?}",
+                                                        field)))
+            }
+        }
+    }
+    Ok(result)
+}
+
+// Check that we were given the right number of parameters.
+fn check_params(name: &str, params: &[f64], count: usize) -> Result<()> {
+    if params.len() != count {
+        return Err(Error::InvalidConfig(format!("Scheduler {} expects {} parameters, got {}",
+                                                name,
+                                                count,
+                                                params.len())));
+    }
+    Ok(())
+}
+
+/// The original stenome scheduler.  The interval is multiplied by a fixed factor for each grade,
+/// and then randomly fuzzed so that problems learned together spread out over time.  For a correct
+/// answer, the problem's ease scales how far the factor is from 1, so that an easy problem grows
+/// faster and a hard one slower, but a factor above 1 always grows the interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Multiplier {
+    /// The multipliers applied to the interval for each of the grades 1..4.
+    pub factors: [f64; 4],
+    /// The relative amount of uniform random fuzz to apply, 0.25 means +/- 25%.
+    pub fuzz: f64,
+}
+
+impl Default for Multiplier {
+    fn default() -> Multiplier {
+        Multiplier {
+            factors: [0.25, 0.9, 1.2, 2.2],
+            fuzz: 0.25,
+        }
+    }
+}
+
+impl Multiplier {
+    fn from_params(params: &[f64]) -> Result<Multiplier> {
+        check_params("multiplier", params, 5)?;
+        Ok(Multiplier {
+            factors: [params[0], params[1], params[2], params[3]],
+            fuzz: params[4],
+        })
+    }
+}
+
+impl Scheduler for Multiplier {
+    fn name(&self) -> &'static str {
+        "multiplier"
+    }
+
+    fn params(&self) -> Vec<f64> {
+        let mut result = self.factors.to_vec();
+        result.push(self.fuzz);
+        result
+    }
+
+    fn schedule(&self, prior: Schedule, _elapsed: f64, grade: u8, rng: &mut Rng) -> Schedule {
+        let factor = self.factors[grade as usize - 1];
+        let factor = if grade > 1 {
+            1.0 + (factor - 1.0) * prior.ease
+        } else {
+            factor * prior.ease
+        };
+        Schedule {
+            interval: prior.interval * factor * fuzz(self.fuzz, rng),
+            ease: adjust_ease(prior.ease, grade),
+        }
+    }
+
+    fn tunable(&self) -> Vec<usize> {
+        vec![0, 1, 2, 3]
+    }
+}
+
+/// A variant of the SuperMemo SM-2 algorithm.  Grades 1..4 map onto SM-2 qualities 1, 3, 4, 5.
+/// A failed problem is reset to the minimum interval, and a successful one grows by its ease
+/// factor, adjusted by the SM-2 quality formula.  A problem's ease factor is `ease` times the
+/// problem's ease.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sm2 {
+    /// The ease factor of a new problem, the base multiplier for a successful answer.
+    pub ease: f64,
+    /// The lowest the ease factor will be adjusted to.
+    pub min_ease: f64,
+    /// The relative amount of uniform random fuzz to apply.
+    pub fuzz: f64,
+}
+
+impl Default for Sm2 {
+    fn default() -> Sm2 {
+        Sm2 {
+            ease: 2.5,
+            min_ease: 1.3,
+            fuzz: 0.05,
+        }
+    }
+}
+
+impl Sm2 {
+    fn from_params(params: &[f64]) -> Result<Sm2> {
+        check_params("sm2", params, 3)?;
+        Ok(Sm2 {
+            ease: params[0],
+            min_ease: params[1],
+            fuzz: params[2],
+        })
+    }
+
+    /// The ease factor after answering with the given grade.
+    pub fn adjust_ease(&self, ease: f64, grade: u8) -> f64 {
+        let q = sm2_quality(grade);
+        (ease + 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02)).max(self.min_ease)
+    }
+}
+
+// Map our 1..4 grade onto the SM-2 0..5 quality scale.
+fn sm2_quality(grade: u8) -> f64 {
+    match grade {
+        1 => 1.0,
+        2 => 3.0,
+        3 => 4.0,
+        _ => 5.0,
+    }
+}
+
+impl Scheduler for Sm2 {
+    fn name(&self) -> &'static str {
+        "sm2"
+    }
+
+    fn params(&self) -> Vec<f64> {
+        vec![self.ease, self.min_ease, self.fuzz]
+    }
+
+    fn schedule(&self, prior: Schedule, elapsed: f64, grade: u8, rng: &mut Rng) -> Schedule {
+        let factor = self.adjust_ease(self.ease * prior.ease, grade);
+        let ease = (factor / self.ease).clamp(MIN_EASE, MAX_EASE);
+        if grade == 1 {
+            return Schedule {
+                interval: 0.0,
+                ease: ease,
+            };
+        }
+        // Answering late, but correctly, is credited with the time actually waited.
+        let base = prior.interval.max(elapsed);
+        Schedule {
+            interval: base * factor * fuzz(self.fuzz, rng),
+            ease: ease,
+        }
+    }
+
+    fn tunable(&self) -> Vec<usize> {
+        vec![0, 1]
+    }
+}
+
+/// A scheduler based on the FSRS (Free Spaced Repetition Scheduler) memory model.  The interval
+/// of a problem is taken as its memory stability, the time at which recall probability drops to
+/// the desired retention.  Stability is then updated using the FSRS v4 formulas for recall and
+/// forgetting.  Times in the formulas are in days, as in FSRS.  The difficulty of a problem is
+/// `difficulty` divided by the problem's ease.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fsrs {
+    /// The probability of recall at which problems should be asked again.
+    pub retention: f64,
+    /// The difficulty of a problem with an ease of 1.0, from 1 (easy) to 10 (hard).
+    pub difficulty: f64,
+    /// The FSRS v4 weights w8 through w16, which control stability growth.
+    pub weights: [f64; 9],
+    /// The relative amount of uniform random fuzz to apply.
+    pub fuzz: f64,
+}
+
+impl Default for Fsrs {
+    fn default() -> Fsrs {
+        Fsrs {
+            retention: 0.9,
+            difficulty: 5.0,
+            weights: [1.49, 0.14, 0.94, 2.18, 0.05, 0.34, 1.26, 0.29, 2.61],
+            fuzz: 0.05,
+        }
+    }
+}
+
+impl Fsrs {
+    fn from_params(params: &[f64]) -> Result<Fsrs> {
+        check_params("fsrs", params, 12)?;
+        let mut weights = [0.0; 9];
+        weights.copy_from_slice(&params[2..11]);
+        Ok(Fsrs {
+            retention: params[0],
+            difficulty: params[1],
+            weights: weights,
+            fuzz: params[11],
+        })
+    }
+
+    /// The probability of recalling a problem of the given stability after `elapsed` time.
+    pub fn retrievability(&self, stability: f64, elapsed: f64) -> f64 {
+        1.0 / (1.0 + elapsed / (9.0 * stability))
+    }
+
+    // The number of stabilities until recall drops to the desired retention.
+    fn interval_factor(&self) -> f64 {
+        9.0 * (1.0 / self.retention - 1.0)
+    }
+
+    /// The difficulty of a problem with the given ease.
+    pub fn difficulty(&self, ease: f64) -> f64 {
+        (self.difficulty / ease).clamp(1.0, 10.0)
+    }
+
+    /// Compute the new stability (in days) from the old one after a review with the given grade.
+    pub fn next_stability(&self, stability: f64, d: f64, elapsed: f64, grade: u8) -> f64 {
+        let w = &self.weights;
+        let r = self.retrievability(stability, elapsed);
+        if grade == 1 {
+            let forget = w[3] * d.powf(-w[4]) * ((stability + 1.0).powf(w[5]) - 1.0) *
+                         (w[6] * (1.0 - r)).exp();
+            forget.min(stability)
+        } else {
+            let hard = if grade == 2 { w[7] } else { 1.0 };
+            let easy = if grade == 4 { w[8] } else { 1.0 };
+            stability *
+            (w[0].exp() * (11.0 - d) * stability.powf(-w[1]) * ((w[2] * (1.0 - r)).exp() - 1.0) *
+             hard * easy + 1.0)
+        }
+    }
+}
+
+impl Scheduler for Fsrs {
+    fn name(&self) -> &'static str {
+        "fsrs"
+    }
+
+    fn params(&self) -> Vec<f64> {
+        let mut result = vec![self.retention, self.difficulty];
+        result.extend_from_slice(&self.weights);
+        result.push(self.fuzz);
+        result
+    }
+
+    fn schedule(&self, prior: Schedule, elapsed: f64, grade: u8, rng: &mut Rng) -> Schedule {
+        let factor = self.interval_factor();
+        let stability = prior.interval / factor / DAY;
+        let stability = self.next_stability(stability,
+                                            self.difficulty(prior.ease),
+                                            elapsed.max(0.0) / DAY,
+                                            grade);
+        Schedule {
+            interval: stability * factor * DAY * fuzz(self.fuzz, rng),
+            ease: adjust_ease(prior.ease, grade),
+        }
+    }
+
+    fn recall(&self, prior: Schedule, elapsed: f64) -> f64 {
+        forgetting_curve(self.retention, prior.interval, elapsed)
+    }
+
+    // The retention is the user's choice, not a property of their memory.
+    fn tunable(&self) -> Vec<usize> {
+        (1..11).collect()
+    }
+}
+
+// Generate a random fuzz multiplier.
+fn fuzz(amount: f64, rng: &mut Rng) -> f64 {
+    if amount > 0.0 {
+        1.0 - amount + 2.0 * amount * rng.next_f64()
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::XorShiftRng;
+
+    // A correct answer after a lapse should grow the interval again, even though the lapse has
+    // lowered the ease.
+    #[test]
+    fn lapse_recovery() {
+        let mut rng = XorShiftRng::new_unseeded();
+        let sched = Multiplier { fuzz: 0.0, ..Multiplier::default() };
+        let prior = Schedule {
+            interval: 10.0 * DAY,
+            ease: INITIAL_EASE,
+        };
+        let mut sch = sched.schedule(prior, 10.0 * DAY, 1, &mut rng);
+        assert!(sch.ease < INITIAL_EASE);
+        for _ in 0..5 {
+            let next = sched.schedule(sch, sch.interval, 3, &mut rng);
+            assert!(next.interval > sch.interval);
+            sch = next;
+        }
+    }
+}
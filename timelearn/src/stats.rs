@@ -0,0 +1,313 @@
+//! Statistics from the review log.
+//!
+//! The log records every answer given, so it can tell how well problems are actually being
+//! remembered, as opposed to how well the scheduler expects them to be.  The interval of a review
+//! is the time since the previous answer to the same problem, and the first answer to a problem
+//! is when it was introduced.
+//!
+//! The statistics only look at the grades in the log, and not at the phase of learning each
+//! answer was given in, so a problem's fails include those in its learning and relearning steps.
+//! These aren't lapses, which are only counted for problems being reviewed.
+
+use rusqlite::Connection;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use {BucketSpec, Result, DAY};
+
+/// Problems that have been successfully recalled after this interval are considered mature.
+pub const MATURE_INTERVAL: f64 = 21.0 * DAY;
+
+/// Statistics computed from the log, by `Store::get_stats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// The total number of answers in the log.
+    pub reviews: usize,
+    /// The average grade of all of the answers, if there are any.
+    pub average_grade: Option<f64>,
+    /// The retention of reviews, grouped by the interval of the review.  The bands are the same as
+    /// the buckets of `Counts`, as configured with `Store::set_buckets`.
+    pub retention: Vec<Retention>,
+    /// The number of reviews and new problems on each day that had any, in order.
+    pub days: Vec<DayStats>,
+    /// How long problems take to become mature.
+    pub maturity: Maturity,
+    /// The problems that have been failed after being introduced, most fails first.
+    pub fails: Vec<ProblemFails>,
+}
+
+/// The retention of reviews with intervals in a single band.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Retention {
+    /// A short description of the band.
+    pub name: String,
+    /// The number of reviews in this band.
+    pub reviews: usize,
+    /// The number of those reviews that weren't forgotten (graded above 1).
+    pub recalled: usize,
+}
+
+impl Retention {
+    /// The fraction of reviews that were recalled, if there were any.
+    pub fn rate(&self) -> Option<f64> {
+        if self.reviews == 0 {
+            None
+        } else {
+            Some(self.recalled as f64 / self.reviews as f64)
+        }
+    }
+}
+
+/// The work done on a single day.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DayStats {
+    /// The time the day starts.
+    pub day: f64,
+    /// The number of answers given, including new problems.
+    pub reviews: usize,
+    /// The number of problems introduced.
+    pub new: usize,
+}
+
+/// How long problems take to become mature, measured from when they are introduced until they are
+/// first recalled after an interval of at least `MATURE_INTERVAL`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Maturity {
+    /// The number of problems that have become mature.
+    pub matured: usize,
+    /// The average time taken to mature, if any have.
+    pub mean_time: Option<f64>,
+    /// The median time taken to mature, if any have.
+    pub median_time: Option<f64>,
+}
+
+/// The number of fails of a single problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProblemFails {
+    /// The problem's question.
+    pub question: String,
+    /// The number of times the problem was answered with a 1 after it was introduced.
+    pub fails: usize,
+}
+
+/// Compute statistics from the log of `profile`, only including problems with the tag `tag`, if
+/// given.  The retention is grouped into `buckets`, and the days are computed with `day_start`,
+/// which gives the start of the day holding a given time.
+pub fn compute(conn: &Connection,
+               tag: Option<&str>,
+               profile: &str,
+               buckets: &[BucketSpec],
+               day_start: &Fn(f64) -> f64)
+               -> Result<Stats> {
+    let mut stats = Stats {
+        reviews: 0,
+        average_grade: None,
+        retention: buckets.iter()
+            .map(|buk| {
+                Retention {
+                    name: buk.name.clone(),
+                    reviews: 0,
+                    recalled: 0,
+                }
+            })
+            .collect(),
+        days: vec![],
+        maturity: Maturity {
+            matured: 0,
+            mean_time: None,
+            median_time: None,
+        },
+        fails: vec![],
+    };
+
+    let mut stmt = conn.prepare("
+        SELECT stamp, score, probid, question
+        FROM log JOIN probs
+        WHERE log.probid = probs.id
+            AND (?1 IS NULL OR probid IN (SELECT probid FROM profile_tags
+                                          WHERE profile = ?2 AND tag = ?1))
+            AND profile = ?2
+        ORDER BY probid, stamp")?;
+    let rows = stmt.query_map(&[&tag, &profile], |row| {
+            let stamp: f64 = row.get(0);
+            let score: u8 = row.get(1);
+            let probid: i64 = row.get(2);
+            let question: String = row.get(3);
+            (stamp, score, probid, question)
+        })?;
+
+    let mut total_grade = 0u64;
+    let mut days: HashMap<i64, DayStats> = HashMap::new();
+    let mut maturity_times = vec![];
+
+    // The state of the current problem: its id, when it was introduced, when it was last
+    // answered, and whether it has matured.
+    let mut cur: Option<(i64, f64, f64, bool)> = None;
+    for row in rows {
+        let (stamp, score, probid, question) = row?;
+        stats.reviews += 1;
+        total_grade += score as u64;
+
+        let day = day_start(stamp);
+        let entry = days.entry(day as i64).or_insert(DayStats {
+                                                         day: day,
+                                                         reviews: 0,
+                                                         new: 0,
+                                                     });
+        entry.reviews += 1;
+
+        match cur {
+            Some((id, first, last, matured)) if id == probid => {
+                let interval = stamp - last;
+                let band = buckets.iter()
+                    .position(|buk| interval <= buk.limit)
+                    .unwrap_or(buckets.len() - 1);
+                let ret = &mut stats.retention[band];
+                ret.reviews += 1;
+                if score > 1 {
+                    ret.recalled += 1;
+                }
+
+                if score == 1 {
+                    match stats.fails.last_mut() {
+                        Some(ref mut fail) if fail.question == question => fail.fails += 1,
+                        _ => {
+                            stats.fails.push(ProblemFails {
+                                                 question: question,
+                                                 fails: 1,
+                                             })
+                        }
+                    }
+                }
+
+                let now_mature = !matured && score > 1 && interval >= MATURE_INTERVAL;
+                if now_mature {
+                    maturity_times.push(stamp - first);
+                }
+                cur = Some((id, first, stamp, matured || now_mature));
+            }
+            _ => {
+                entry.new += 1;
+                cur = Some((probid, stamp, stamp, false));
+            }
+        }
+    }
+
+    if stats.reviews > 0 {
+        stats.average_grade = Some(total_grade as f64 / stats.reviews as f64);
+    }
+
+    stats.days = days.into_values().collect();
+    stats.days.sort_by(|a, b| a.day.partial_cmp(&b.day).unwrap());
+
+    if !maturity_times.is_empty() {
+        maturity_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let count = maturity_times.len();
+        stats.maturity.matured = count;
+        stats.maturity.mean_time = Some(maturity_times.iter().sum::<f64>() / count as f64);
+        stats.maturity.median_time = Some(if count % 2 == 1 {
+                                              maturity_times[count / 2]
+                                          } else {
+                                              (maturity_times[count / 2 - 1] +
+                                               maturity_times[count / 2]) /
+                                              2.0
+                                          });
+    }
+
+    stats.fails.sort_by_key(|fail| Reverse(fail.fails));
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {test_db, Stage};
+
+    // A database holding the given answers of the default profile, each a problem, a time and a
+    // grade.  Problem 2 is tagged "odd".
+    fn log(answers: &[(i64, f64, u8)]) -> Connection {
+        let conn = test_db();
+        conn.execute_batch("
+            INSERT INTO probs (id, question, answer) VALUES (1, 'q1', ''), (2, 'q2', ''),
+                (3, 'q3', '');
+            INSERT INTO tags (probid, tag) VALUES (2, 'odd');")
+            .unwrap();
+        for &(probid, stamp, score) in answers {
+            conn.execute("INSERT INTO log (stamp, score, probid, profile)
+                          VALUES (?, ?, ?, 'default')",
+                         &[&stamp, &(score as i64), &probid])
+                .unwrap();
+        }
+        conn
+    }
+
+    fn day_start(time: f64) -> f64 {
+        (time / DAY).floor() * DAY
+    }
+
+    #[test]
+    fn retention_rate() {
+        let mut ret = Retention {
+            name: "all".to_owned(),
+            reviews: 0,
+            recalled: 0,
+        };
+        assert_eq!(ret.rate(), None);
+        ret.reviews = 4;
+        ret.recalled = 3;
+        assert_eq!(ret.rate(), Some(0.75));
+    }
+
+    #[test]
+    fn compute_log() {
+        let conn = log(&[(1, 0.0, 3),
+                         (1, 10.0, 1),
+                         (1, 10.0 + 30.0 * DAY, 3),
+                         (2, 5.0, 2),
+                         (3, DAY, 4),
+                         (3, 26.0 * DAY, 4),
+                         (3, 26.0 * DAY + 1.0, 1),
+                         (3, 26.0 * DAY + 2.0, 1)]);
+        // Answers of other profiles are left out.
+        conn.execute_batch("INSERT INTO log VALUES (20.0, 4, 1, 'other')").unwrap();
+        let buckets = vec![BucketSpec::new("short", DAY, Stage::Active),
+                           BucketSpec::new("long", 100.0 * DAY, Stage::Learned)];
+
+        let stats = compute(&conn, None, "default", &buckets, &day_start).unwrap();
+        assert_eq!(stats.reviews, 8);
+        assert_eq!(stats.average_grade, Some(19.0 / 8.0));
+        let retention: Vec<_> = stats.retention
+            .iter()
+            .map(|r| (r.name.as_str(), r.reviews, r.recalled))
+            .collect();
+        assert_eq!(retention, vec![("short", 3, 0), ("long", 2, 2)]);
+        let days: Vec<_> = stats.days.iter().map(|d| (d.day, d.reviews, d.new)).collect();
+        assert_eq!(days,
+                   vec![(0.0, 3, 2), (DAY, 1, 1), (26.0 * DAY, 3, 0), (30.0 * DAY, 1, 0)]);
+
+        // With an even number of times, the median is halfway between the middle two.
+        let time = (10.0 + 30.0 * DAY + 25.0 * DAY) / 2.0;
+        assert_eq!(stats.maturity,
+                   Maturity {
+                       matured: 2,
+                       mean_time: Some(time),
+                       median_time: Some(time),
+                   });
+        let fails: Vec<_> = stats.fails.iter().map(|f| (f.question.as_str(), f.fails)).collect();
+        assert_eq!(fails, vec![("q3", 2), ("q1", 1)]);
+
+        let stats = compute(&conn, Some("odd"), "default", &buckets, &day_start).unwrap();
+        assert_eq!((stats.reviews, stats.average_grade), (1, Some(2.0)));
+        assert_eq!(stats.retention.iter().map(|r| r.reviews).sum::<usize>(), 0);
+        assert_eq!(stats.maturity.matured, 0);
+    }
+
+    #[test]
+    fn compute_empty() {
+        let buckets = vec![BucketSpec::new("all", DAY, Stage::Active)];
+        let stats = compute(&log(&[]), None, "default", &buckets, &day_start).unwrap();
+        assert_eq!((stats.reviews, stats.average_grade), (0, None));
+        assert_eq!(stats.days, vec![]);
+        assert_eq!(stats.maturity.median_time, None);
+    }
+}
@@ -0,0 +1,363 @@
+//! Learning steps.
+//!
+//! Rather than handing a new problem straight to the scheduler, it can first be taken through a
+//! series of short, fixed learning steps (for example 1 minute, 10 minutes and 1 hour), much as
+//! Anki does.  Each correct answer moves the problem on to the next step, and answering after the
+//! last step graduates it to normal scheduling.  Likewise, a learned problem that is forgotten
+//! goes through the relearning steps before it returns to its (reduced) interval.  With no steps
+//! configured, problems go straight to the scheduler, as they always have.
+//!
+//! While a problem is in its steps, its answers are graded as follows:
+//!
+//! - 1: Go back to the first step.
+//! - 2: Repeat the current step.
+//! - 3: Move on to the next step, graduating after the last one.
+//! - 4: Graduate immediately.
+//!
+//! The steps are kept in the `config` table, as comma separated lists of intervals in seconds.
+
+use rusqlite::Connection;
+use {get_config, set_config, Error, Result, Schedule};
+
+/// Where a problem is in the learning process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// The problem has never been asked.
+    New,
+    /// The problem is being learned, and is at the given index of the learning steps.
+    Learning(usize),
+    /// The problem has been learned, and is scheduled by the scheduler.
+    Review,
+    /// The problem was forgotten, and is at the given index of the relearning steps.
+    Relearning(usize),
+}
+
+/// Decode a phase from the `phase` and `step` columns of the learning table.  The table only
+/// allows the known phases.
+pub fn decode_phase(phase: &str, step: u32) -> Phase {
+    match phase {
+        "learning" => Phase::Learning(step as usize),
+        "relearning" => Phase::Relearning(step as usize),
+        _ => Phase::Review,
+    }
+}
+
+/// Encode a phase as the `phase` and `step` columns of the learning table.  New problems don't
+/// have a row in the learning table, and are written as they will be once they are answered.
+pub fn encode_phase(phase: Phase) -> (&'static str, u32) {
+    match phase {
+        Phase::New | Phase::Review => ("review", 0),
+        Phase::Learning(step) => ("learning", step as u32),
+        Phase::Relearning(step) => ("relearning", step as u32),
+    }
+}
+
+/// The learning and relearning steps, each an interval in seconds.  This is kept in the `config`
+/// table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Steps {
+    /// The steps new problems go through before they are scheduled normally.
+    pub learning: Vec<f64>,
+    /// The steps forgotten problems go through before they return to normal scheduling.
+    pub relearning: Vec<f64>,
+}
+
+/// The learning state of a problem after it has been answered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Answer {
+    /// The phase the problem is now in.
+    pub phase: Phase,
+    /// The interval, and ease, to schedule the problem with.
+    pub schedule: Schedule,
+    /// For a problem that is relearning, the interval it will have when it graduates.
+    pub graduate: Option<f64>,
+}
+
+/// Compute the new learning state of a problem that was in `phase`, with the schedule `prior`,
+/// after it has been answered with `grade`.  `graduate` is the interval a relearning problem will
+/// return to.  `schedule` computes the next schedule with the scheduler, given the prior one.
+pub fn answer(steps: &Steps,
+              phase: Phase,
+              prior: Schedule,
+              graduate: Option<f64>,
+              grade: u8,
+              schedule: &mut FnMut(Schedule) -> Schedule)
+              -> Answer {
+    match phase {
+        Phase::New if !steps.learning.is_empty() => {
+            match step(&steps.learning, 0, grade, prior.ease, Phase::Learning) {
+                Some(answer) => answer,
+                None => graduate_learning(&steps.learning, prior, schedule),
+            }
+        }
+        Phase::Learning(index) => {
+            match step(&steps.learning, index, grade, prior.ease, Phase::Learning) {
+                Some(answer) => answer,
+                None => graduate_learning(&steps.learning, prior, schedule),
+            }
+        }
+        Phase::Relearning(index) => {
+            match step(&steps.relearning, index, grade, prior.ease, Phase::Relearning) {
+                Some(answer) => answer,
+                None => {
+                    Answer {
+                        phase: Phase::Review,
+                        schedule: Schedule {
+                            interval: graduate.unwrap_or(prior.interval),
+                            ease: prior.ease,
+                        },
+                        graduate: None,
+                    }
+                }
+            }
+        }
+        Phase::Review if grade == 1 && !steps.relearning.is_empty() => {
+            // The scheduler decides how much the interval and ease are reduced by the lapse, and
+            // the problem returns to that interval once it is relearned.
+            let lapsed = schedule(prior);
+            Answer {
+                phase: Phase::Relearning(0),
+                schedule: Schedule {
+                    interval: steps.relearning[0],
+                    ease: lapsed.ease,
+                },
+                graduate: Some(lapsed.interval),
+            }
+        }
+        Phase::New | Phase::Review => {
+            Answer {
+                phase: Phase::Review,
+                schedule: schedule(prior),
+                graduate: None,
+            }
+        }
+    }
+}
+
+// Move a problem at `index` of `steps` to its next step, or return `None` if it should graduate.
+// The ease doesn't change while a problem is in its steps.
+fn step(steps: &[f64],
+        index: usize,
+        grade: u8,
+        ease: f64,
+        phase: fn(usize) -> Phase)
+        -> Option<Answer> {
+    let next = match grade {
+        1 => 0,
+        2 => index,
+        3 => index + 1,
+        _ => return None,
+    };
+    steps.get(next).map(|&interval| {
+        Answer {
+            phase: phase(next),
+            schedule: Schedule {
+                interval: interval,
+                ease: ease,
+            },
+            graduate: None,
+        }
+    })
+}
+
+// Graduate a problem from its learning steps, letting the scheduler grow its interval from the
+// last step.
+fn graduate_learning(steps: &[f64],
+                     prior: Schedule,
+                     schedule: &mut FnMut(Schedule) -> Schedule)
+                     -> Answer {
+    Answer {
+        phase: Phase::Review,
+        schedule: schedule(Schedule {
+                               interval: steps.last().cloned().unwrap_or(prior.interval),
+                               ease: prior.ease,
+                           }),
+        graduate: None,
+    }
+}
+
+static LEARNING_STEPS: &'static str = "learning-steps";
+static RELEARNING_STEPS: &'static str = "relearning-steps";
+
+/// Read the steps from the config table.
+pub fn get_steps(conn: &Connection) -> Result<Steps> {
+    Ok(Steps {
+           learning: get_list(conn, LEARNING_STEPS)?,
+           relearning: get_list(conn, RELEARNING_STEPS)?,
+       })
+}
+
+/// Write the steps to the config table.
+pub fn set_steps(conn: &Connection, steps: &Steps) -> Result<()> {
+    set_list(conn, LEARNING_STEPS, &steps.learning)?;
+    set_list(conn, RELEARNING_STEPS, &steps.relearning)?;
+    Ok(())
+}
+
+fn get_list(conn: &Connection, key: &str) -> Result<Vec<f64>> {
+    let text = match get_config(conn, key)? {
+        None => return Ok(vec![]),
+        Some(text) => text,
+    };
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut result = vec![];
+    for field in text.split(',') {
+        match field.trim().parse() {
+            Ok(value) => result.push(value),
+            Err(_) => return Err(Error::InvalidConfig(format!("{}: {:?}", key, text))),
+        }
+    }
+    check_list(key, &result)?;
+    Ok(result)
+}
+
+fn set_list(conn: &Connection, key: &str, list: &[f64]) -> Result<()> {
+    check_list(key, list)?;
+    let text: Vec<_> = list.iter().map(|s| s.to_string()).collect();
+    set_config(conn, key, &text.join(","))
+}
+
+fn check_list(key: &str, list: &[f64]) -> Result<()> {
+    for &step in list {
+        if !(step > 0.0) || !step.is_finite() {
+            return Err(Error::InvalidConfig(format!("{}: invalid step {}", key, step)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_db;
+
+    const PRIOR: Schedule = Schedule {
+        interval: 1000.0,
+        ease: 1.0,
+    };
+
+    // A stand in for the scheduler, that doubles the interval and raises the ease.
+    fn double(prior: Schedule) -> Schedule {
+        Schedule {
+            interval: prior.interval * 2.0,
+            ease: prior.ease + 0.5,
+        }
+    }
+
+    fn run(steps: &Steps, phase: Phase, graduate: Option<f64>, grade: u8) -> Answer {
+        answer(steps, phase, PRIOR, graduate, grade, &mut double)
+    }
+
+    fn stepped(phase: Phase, interval: f64, graduate: Option<f64>) -> Answer {
+        Answer {
+            phase: phase,
+            schedule: Schedule {
+                interval: interval,
+                ease: PRIOR.ease,
+            },
+            graduate: graduate,
+        }
+    }
+
+    fn scheduled(schedule: Schedule) -> Answer {
+        Answer {
+            phase: Phase::Review,
+            schedule: schedule,
+            graduate: None,
+        }
+    }
+
+    // Without steps, every answer goes to the scheduler.
+    #[test]
+    fn no_steps() {
+        let steps = Steps::default();
+        assert_eq!(run(&steps, Phase::New, None, 3), scheduled(double(PRIOR)));
+        assert_eq!(run(&steps, Phase::Review, None, 1), scheduled(double(PRIOR)));
+        assert_eq!(run(&steps, Phase::Review, None, 4), scheduled(double(PRIOR)));
+    }
+
+    #[test]
+    fn learning() {
+        let steps = Steps {
+            learning: vec![60.0, 600.0],
+            relearning: vec![],
+        };
+        assert_eq!(run(&steps, Phase::New, None, 1), stepped(Phase::Learning(0), 60.0, None));
+        assert_eq!(run(&steps, Phase::New, None, 3), stepped(Phase::Learning(1), 600.0, None));
+        assert_eq!(run(&steps, Phase::Learning(1), None, 1),
+                   stepped(Phase::Learning(0), 60.0, None));
+        assert_eq!(run(&steps, Phase::Learning(1), None, 2),
+                   stepped(Phase::Learning(1), 600.0, None));
+
+        // Graduating grows the interval from the last step.
+        let graduated = scheduled(double(Schedule {
+                                             interval: 600.0,
+                                             ease: PRIOR.ease,
+                                         }));
+        assert_eq!(run(&steps, Phase::Learning(1), None, 3), graduated);
+        assert_eq!(run(&steps, Phase::Learning(0), None, 4), graduated);
+        assert_eq!(run(&steps, Phase::New, None, 4), graduated);
+    }
+
+    #[test]
+    fn relearning() {
+        let steps = Steps {
+            learning: vec![],
+            relearning: vec![300.0, 900.0],
+        };
+
+        // The lapse is scheduled straight away, and returned to after the steps.
+        let lapsed = double(PRIOR);
+        assert_eq!(run(&steps, Phase::Review, None, 1),
+                   Answer {
+                       phase: Phase::Relearning(0),
+                       schedule: Schedule {
+                           interval: 300.0,
+                           ease: lapsed.ease,
+                       },
+                       graduate: Some(lapsed.interval),
+                   });
+        assert_eq!(run(&steps, Phase::Review, None, 3), scheduled(double(PRIOR)));
+        assert_eq!(run(&steps, Phase::Relearning(0), Some(5000.0), 3),
+                   stepped(Phase::Relearning(1), 900.0, None));
+        assert_eq!(run(&steps, Phase::Relearning(1), Some(5000.0), 3),
+                   stepped(Phase::Review, 5000.0, None));
+        assert_eq!(run(&steps, Phase::Relearning(0), None, 4),
+                   stepped(Phase::Review, PRIOR.interval, None));
+    }
+
+    #[test]
+    fn phases() {
+        for &phase in &[Phase::Review, Phase::Learning(2), Phase::Relearning(0)] {
+            let (name, step) = encode_phase(phase);
+            assert_eq!(decode_phase(name, step), phase);
+        }
+        assert_eq!(encode_phase(Phase::New), ("review", 0));
+    }
+
+    #[test]
+    fn config() {
+        let conn = test_db();
+        assert_eq!(get_steps(&conn).unwrap(), Steps::default());
+
+        let steps = Steps {
+            learning: vec![60.0, 600.5],
+            relearning: vec![],
+        };
+        set_steps(&conn, &steps).unwrap();
+        assert_eq!(get_steps(&conn).unwrap(), steps);
+
+        assert!(set_steps(&conn,
+                          &Steps {
+                              learning: vec![-1.0],
+                              relearning: vec![],
+                          })
+            .is_err());
+        for text in &["60,", "x", "0", "inf"] {
+            set_config(&conn, LEARNING_STEPS, text).unwrap();
+            assert!(get_steps(&conn).is_err(), "accepted {:?}", text);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Synchronizing a store with a regenerated list of problems.
+//!
+//! Problem lists are usually generated, and when the generator changes, the whole list is
+//! generated again.  A `Syncer` is given the complete new list, and matches it against the
+//! existing problems by question.  Problems whose answers have changed are updated, new problems
+//! are added, and problems that are no longer in the list are handled according to the `Missing`
+//! policy.  The learning state and log of problems that remain in the list are kept, and their
+//! tags are replaced with those given in the new list.
+
+use rusqlite::{self, Connection, Transaction};
+use std::collections::HashSet;
+use {Error, Result};
+
+/// The tag given to problems that have been retired by a sync.
+pub const RETIRED_TAG: &'static str = "retired";
+
+/// What to do with existing problems that aren't in the new problem list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Missing {
+    /// Leave them as they are, to be asked as before.
+    Keep,
+    /// Suspend them for every profile, and tag them with `RETIRED_TAG`.  Their learning state and
+    /// log are kept, and they are brought back if they reappear in a later sync.  Profiles added
+    /// later start out with them suspended too.
+    Retire,
+    /// Delete them, along with their learning state and log.
+    Delete,
+}
+
+/// A summary of the changes made by a sync.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// The number of problems that were added.
+    pub added: usize,
+    /// The number of problems whose answer changed.
+    pub updated: usize,
+    /// The number of problems that were unchanged.
+    pub unchanged: usize,
+    /// The number of previously retired problems that were brought back.
+    pub restored: usize,
+    /// The number of problems that were retired.
+    pub retired: usize,
+    /// The number of problems that were deleted.
+    pub deleted: usize,
+}
+
+/// A helper to synchronize a `Store` with a new list of problems, created by `Store::sync`.  Every
+/// problem in the new list should be given to `add_problem`, and then `finish` called to deal with
+/// the problems that weren't given.
+pub struct Syncer<'a> {
+    tx: Transaction<'a>,
+    seen: HashSet<i64>,
+    report: SyncReport,
+}
+
+/// Start a sync within the given transaction.
+pub fn start(tx: Transaction) -> Syncer {
+    Syncer {
+        tx: tx,
+        seen: HashSet::new(),
+        report: SyncReport::default(),
+    }
+}
+
+impl<'a> Syncer<'a> {
+    /// Add a problem from the new list.  If a problem with this question already exists, its
+    /// answer is updated, otherwise it is added as a new, unlearned problem.
+    pub fn add_problem(&mut self, question: &str, answer: &str) -> Result<()> {
+        let old = match self.tx
+                  .query_row("SELECT id, answer FROM probs WHERE question = ?",
+                             &[&question],
+                             |row| {
+                                 let id: i64 = row.get(0);
+                                 let answer: String = row.get(1);
+                                 (id, answer)
+                             }) {
+            Ok(old) => Some(old),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        match old {
+            None => {
+                self.tx
+                    .execute("INSERT INTO probs (question, answer) VALUES (?, ?)",
+                             &[&question, &answer])?;
+                self.seen.insert(self.tx.last_insert_rowid());
+                self.report.added += 1;
+            }
+            Some((id, old_answer)) => {
+                if !self.seen.insert(id) {
+                    return Err(Error::DuplicateProblem(question.to_owned()));
+                }
+                // The problem's tags are replaced with those given to `add_tags`.  Being retired
+                // is handled below.
+                self.tx
+                    .execute("DELETE FROM tags WHERE probid = ? AND tag != ?",
+                             &[&id, &RETIRED_TAG])?;
+                if old_answer != answer {
+                    self.tx
+                        .execute("UPDATE probs SET answer = ? WHERE id = ?",
+                                 &[&answer, &id])?;
+                    self.report.updated += 1;
+                } else {
+                    self.report.unchanged += 1;
+                }
+                let retired = self.tx
+                    .execute("DELETE FROM tags WHERE probid = ? AND tag = ?",
+                             &[&id, &RETIRED_TAG])?;
+                if retired > 0 {
+                    self.tx.execute("DELETE FROM suspended WHERE probid = ?", &[&id])?;
+                    self.report.restored += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Attach tags to a problem from the new list, which must have already been given to
+    /// `add_problem`.  The tags replace those the problem had before the sync.
+    pub fn add_tags(&mut self, question: &str, tags: &[&str]) -> Result<()> {
+        ::add_tags(&self.tx, question, tags)
+    }
+
+    /// Handle the problems that weren't given to `add_problem` according to `missing`, and
+    /// commit.  If the `Syncer` is dropped without calling `finish`, no changes are made.
+    pub fn finish(mut self, missing: Missing) -> Result<SyncReport> {
+        let mut gone = vec![];
+        {
+            let mut stmt = self.tx.prepare("SELECT id FROM probs")?;
+            let rows = stmt.query_map(&[], |row| {
+                    let id: i64 = row.get(0);
+                    id
+                })?;
+            for id in rows {
+                let id = id?;
+                if !self.seen.contains(&id) {
+                    gone.push(id);
+                }
+            }
+        }
+
+        for id in &gone {
+            match missing {
+                Missing::Keep => (),
+                Missing::Retire => {
+                    // Problems retired by an earlier sync are left alone.
+                    let tagged = self.tx
+                        .execute("INSERT OR IGNORE INTO tags (probid, tag) VALUES (?, ?)",
+                                 &[id, &RETIRED_TAG])?;
+                    if tagged > 0 {
+                        self.tx
+                            .execute("INSERT OR REPLACE INTO suspended (profile, probid, until)
+                                      SELECT name, ?, NULL FROM profiles",
+                                     &[id])?;
+                        self.report.retired += 1;
+                    }
+                }
+                Missing::Delete => {
+                    self.tx.execute("DELETE FROM tags WHERE probid = ?", &[id])?;
+                    self.tx.execute("DELETE FROM suspended WHERE probid = ?", &[id])?;
+                    self.tx.execute("DELETE FROM learning WHERE probid = ?", &[id])?;
+                    self.tx.execute("DELETE FROM imported WHERE probid = ?", &[id])?;
+                    self.tx.execute("DELETE FROM leeches WHERE probid = ?", &[id])?;
+                    self.tx.execute("DELETE FROM log WHERE probid = ?", &[id])?;
+                    self.tx.execute("DELETE FROM probs WHERE id = ?", &[id])?;
+                    self.report.deleted += 1;
+                }
+            }
+        }
+
+        self.tx.commit()?;
+        Ok(self.report)
+    }
+}
+
+/// Suspend the problems that a sync has retired for `profile`, which has just been added.
+pub fn suspend_retired(conn: &Connection, profile: &str) -> Result<()> {
+    conn.execute("INSERT OR REPLACE INTO suspended (profile, probid, until)
+                  SELECT ?, probid, NULL FROM tags WHERE tag = ?",
+                 &[&profile, &RETIRED_TAG])?;
+    Ok(())
+}
@@ -1,13 +1,21 @@
 // Test the learning code.
 
 extern crate rand;
+extern crate rusqlite;
 extern crate tempdir;
 extern crate timelearn;
 
 use rand::{Rng, SeedableRng, XorShiftRng};
+use rusqlite::Connection;
 use tempdir::TempDir;
-use timelearn::{now, Populator, Store, Result};
+use timelearn::scheduler::from_config;
+use timelearn::{now, BucketSpec, Budget, Clock, Error, Granularity, LeechPolicy, Limits,
+                ManualClock, Missing, Multiplier, Phase, Populator, Problem, Rollover, Scheduler,
+                Sm2, Stage, Steps, Store, SyncReport, Result, DEFAULT_PROFILE, INITIAL_EASE,
+                LEECH_TAG, SCHEMA_VERSION};
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
 #[test]
 fn learning() {
@@ -53,6 +61,1044 @@ fn learning() {
     assert_eq!(st.get_kind(), "test");
 }
 
+#[test]
+fn scheduler_config() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    {
+        let mut st = Store::create(&db_path, "test").unwrap();
+        assert_eq!(st.get_scheduler().name(), "multiplier");
+
+        let sched = Sm2 { ease: 2.0, ..Sm2::default() };
+        st.set_scheduler(Box::new(sched)).unwrap();
+    }
+
+    // The scheduler, and its parameters, should be kept with the database.
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_scheduler().name(), "sm2");
+    assert_eq!(st.get_scheduler().params(), Sm2 { ease: 2.0, ..Sm2::default() }.params());
+}
+
+// Simulate several months of daily practice, always answering correctly, and make sure the
+// problems end up being learned.
+#[test]
+fn simulate() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    let clock = ManualClock::new(1.5e9);
+    let mut st = Store::create(&db_path, "test").unwrap();
+    st.set_clock(Box::new(clock.clone()));
+
+    {
+        let mut p = st.populate().unwrap();
+        for i in 1..11 {
+            add_one(&mut p, i, None).unwrap();
+        }
+        p.commit().unwrap();
+    }
+
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.later, counts.unlearned), (0, 0, 10));
+
+    for _ in 0..120 {
+        // Practice until nothing is left, taking 10 seconds for each answer.
+        loop {
+            let prob = match st.get_nexts(1).unwrap().pop() {
+                None => break,
+                Some(p) => p,
+            };
+            st.update(prob, 4).unwrap();
+            clock.advance(10.0);
+        }
+
+        let counts = st.get_counts().unwrap();
+        assert_eq!((counts.active, counts.later, counts.unlearned), (0, 10, 0));
+
+        clock.advance(24.0 * 60.0 * 60.0);
+    }
+
+    // Everything should now have an interval of at least a day.
+    let counts = st.get_counts().unwrap();
+    for b in &counts.buckets {
+        if b.stage == Stage::Active {
+            assert_eq!(b.count, 0);
+        }
+    }
+
+    // And after a long enough time, everything should be due.
+    clock.advance(1.0e9);
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.later, counts.unlearned), (10, 0, 0));
+}
+
+// Problems that are always failed should end up with a lower ease than those always passed.
+#[test]
+fn ease() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    let clock = ManualClock::new(1.5e9);
+    let mut st = Store::create(&db_path, "test").unwrap();
+    st.set_clock(Box::new(clock.clone()));
+
+    {
+        let mut p = st.populate().unwrap();
+        p.add_learning_problem("hard", "hard", clock.now(), 60.0).unwrap();
+        p.add_learning_problem("easy", "easy", clock.now(), 60.0).unwrap();
+        p.commit().unwrap();
+    }
+
+    let mut hard: Option<Problem> = None;
+    let mut easy: Option<Problem> = None;
+    for _ in 0..10 {
+        for prob in st.get_nexts(2).unwrap() {
+            if prob.question == "hard" {
+                st.update(prob, 1).unwrap();
+            } else {
+                st.update(prob, 4).unwrap();
+            }
+        }
+        clock.advance(1.0e9);
+        for prob in st.get_nexts(2).unwrap() {
+            if prob.question == "hard" {
+                hard = Some(prob);
+            } else {
+                easy = Some(prob);
+            }
+        }
+    }
+
+    let hard = hard.unwrap();
+    let easy = easy.unwrap();
+    assert!(hard.get_ease() < INITIAL_EASE);
+    assert!(easy.get_ease() > INITIAL_EASE);
+    assert!(hard.get_interval() < easy.get_interval());
+}
+
+// Sessions should stop introducing new problems, or asking any, when the limits are reached.
+#[test]
+fn limits() {
+    let names: Vec<_> = (1..11).map(|i| format!("q{}", i)).collect();
+    let probs: Vec<_> = names.iter().map(|qn| (qn.as_str(), None)).collect();
+    let Fixture { tmp_dir: _tmp_dir, db_path, clock, mut st } = fixture(1.5e9, &probs);
+    st.set_limits(Limits {
+            new_per_day: Some(3),
+            reviews_per_session: Some(20),
+        })
+        .unwrap();
+
+    // The limits should persist.
+    drop(st);
+    let mut st = Store::open(&db_path).unwrap();
+    st.set_clock(Box::new(clock.clone()));
+    assert_eq!(st.get_limits().new_per_day, Some(3));
+    assert_eq!(st.get_counts().unwrap().budget,
+               Budget {
+                   new_left: Some(3),
+                   reviews_left: Some(20),
+               });
+
+    // Answer everything available, 10 seconds apart.
+    let mut asked = 0;
+    loop {
+        let prob = match st.get_nexts(1).unwrap().pop() {
+            None => break,
+            Some(p) => p,
+        };
+        st.update(prob, 4).unwrap();
+        asked += 1;
+        clock.advance(10.0);
+    }
+    let counts = st.get_counts().unwrap();
+    assert_eq!(counts.unlearned, 7);
+    assert_eq!(counts.budget.new_left, Some(0));
+    assert_eq!(counts.budget.reviews_left, Some(20 - asked));
+    assert_eq!(st.get_budget().unwrap(), counts.budget);
+
+    // The next day, the review limit should stop the session.
+    clock.advance(24.0 * 60.0 * 60.0);
+    assert_eq!(st.get_counts().unwrap().budget.new_left, Some(3));
+    loop {
+        let prob = match st.get_nexts(1).unwrap().pop() {
+            None => break,
+            Some(p) => p,
+        };
+        st.update(prob, 1).unwrap();
+        clock.advance(10.0);
+    }
+    assert!(st.get_budget().unwrap().reviews_exhausted());
+    assert!(st.get_counts().unwrap().budget.reviews_exhausted());
+}
+
+// A tag filter should restrict the problems asked and counted.
+#[test]
+fn tags() {
+    let names: Vec<_> = (1..11).map(|i| format!("q{}", i)).collect();
+    let probs: Vec<_> = names.iter().map(|qn| (qn.as_str(), None)).collect();
+    let Fixture { tmp_dir: _tmp_dir, mut st, .. } = fixture(1.0e9, &probs);
+    {
+        let mut p = st.populate().unwrap();
+        for (i, qn) in (1..).zip(&names) {
+            let hand = if i % 2 == 0 { "left-hand" } else { "right-hand" };
+            p.add_tags(qn, &[hand, "group-1"]).unwrap();
+        }
+        assert!(p.add_tags("missing", &["group-1"]).is_err());
+        p.commit().unwrap();
+    }
+
+    st.set_tag_filter(Some("right-hand"));
+    assert_eq!(st.get_counts().unwrap().unlearned, 5);
+    loop {
+        let prob = match st.get_nexts(1).unwrap().pop() {
+            None => break,
+            Some(p) => p,
+        };
+        let num: u32 = prob.question[1..].split(' ').next().unwrap().parse().unwrap();
+        assert_eq!(num % 2, 1);
+        st.update(prob, 4).unwrap();
+    }
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.later, counts.unlearned), (0, 5, 0));
+
+    st.set_tag_filter(None);
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.later, counts.unlearned), (0, 5, 5));
+}
+
+// New and forgotten problems should go through their steps before being scheduled normally.
+#[test]
+fn steps() {
+    let Fixture { tmp_dir: _tmp_dir, db_path, clock, mut st } = fixture(1.0e9, &[("q", None)]);
+    st.set_steps(Steps {
+            learning: vec![60.0, 600.0],
+            relearning: vec![300.0],
+        })
+        .unwrap();
+
+    // Answer the problem with the given grade, checking the phase it was in.
+    let answer = |st: &mut Store, phase: Phase, grade: u8| {
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        assert_eq!(prob.get_phase(), phase);
+        st.update(prob, grade).unwrap();
+    };
+    // The interval the problem was just given.
+    let interval = |st: &mut Store| {
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        prob.get_interval()
+    };
+
+    answer(&mut st, Phase::New, 3);
+    clock.advance(600.0);
+    assert_eq!(interval(&mut st), 600.0);
+    answer(&mut st, Phase::Learning(1), 1);
+    clock.advance(60.0);
+    assert_eq!(interval(&mut st), 60.0);
+    answer(&mut st, Phase::Learning(0), 2);
+    clock.advance(60.0);
+    answer(&mut st, Phase::Learning(0), 3);
+    clock.advance(600.0);
+    answer(&mut st, Phase::Learning(1), 4);
+
+    // Graduated, the interval grows from the last step.  Forgetting in the steps isn't a lapse.
+    clock.advance(1.0e6);
+    assert!(interval(&mut st) > 600.0);
+    answer(&mut st, Phase::Review, 1);
+    clock.advance(300.0);
+    let prob = st.get_nexts(1).unwrap().pop().unwrap();
+    assert_eq!((prob.get_phase(), prob.get_interval(), prob.get_lapses()),
+               (Phase::Relearning(0), 300.0, 1));
+    st.update(prob, 3).unwrap();
+    clock.advance(1.0e6);
+    let prob = st.get_nexts(1).unwrap().pop().unwrap();
+    assert_eq!((prob.get_phase(), prob.get_lapses()), (Phase::Review, 1));
+
+    // The steps should persist.
+    drop(st);
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_steps().learning, vec![60.0, 600.0]);
+    assert_eq!(st.get_steps().relearning, vec![300.0]);
+}
+
+// A problem that keeps being forgotten should be detected as a leech, and suspended if the policy
+// asks for it.
+#[test]
+fn leeches() {
+    let Fixture { tmp_dir: _tmp_dir, db_path, clock, mut st } = fixture(1.0e9, &[("hard", None)]);
+    st.set_leech_policy(LeechPolicy {
+            threshold: 3,
+            suspend: true,
+        })
+        .unwrap();
+
+    // The first answer is learning the problem, and isn't a lapse.
+    let prob = st.get_nexts(1).unwrap().pop().unwrap();
+    st.update(prob, 1).unwrap();
+    for lapses in 0..3 {
+        clock.advance(60.0);
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        assert_eq!(prob.get_lapses(), lapses);
+        assert!(st.get_leeches().unwrap().is_empty());
+        st.update(prob, 1).unwrap();
+    }
+
+    let leeches = st.get_leeches().unwrap();
+    assert_eq!(leeches.len(), 1);
+    assert_eq!((leeches[0].question.as_str(), leeches[0].lapses, leeches[0].suspended),
+               ("hard", 3, true));
+    assert!(st.get_nexts(1).unwrap().is_empty());
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.later, counts.suspended), (0, 0, 1));
+
+    // The policy should persist.
+    drop(st);
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_leech_policy().threshold, 3);
+}
+
+// Lapses are counted for each profile, so a problem should only be a leech for the profile that
+// keeps forgetting it.
+#[test]
+fn leeches_per_profile() {
+    let Fixture { tmp_dir: _tmp_dir, clock, mut st, .. } = fixture(1.0e9, &[("hard", None)]);
+    st.set_leech_policy(LeechPolicy {
+            threshold: 2,
+            suspend: false,
+        })
+        .unwrap();
+    st.add_profile("kim").unwrap();
+
+    for &(profile, grade) in &[(DEFAULT_PROFILE, 1), ("kim", 3)] {
+        st.set_profile(profile).unwrap();
+        for _ in 0..3 {
+            let prob = st.get_nexts(1).unwrap().pop().unwrap();
+            st.update(prob, grade).unwrap();
+            clock.advance(1.0e7);
+        }
+    }
+
+    // Only the default profile has forgotten the problem, and only it sees the leech tag.
+    st.set_tag_filter(Some(LEECH_TAG));
+    assert!(st.get_leeches().unwrap().is_empty());
+    let counts = st.get_counts().unwrap();
+    assert_eq!(counts.active + counts.later, 0);
+    st.set_profile(DEFAULT_PROFILE).unwrap();
+    let leeches = st.get_leeches().unwrap();
+    assert_eq!((leeches.len(), leeches[0].lapses), (1, 2));
+    let counts = st.get_counts().unwrap();
+    assert_eq!(counts.active + counts.later, 1);
+}
+
+// Suspended problems should be left out until they are unsuspended, and buried ones until the
+// next day.
+#[test]
+fn suspend() {
+    let Fixture { tmp_dir: _tmp_dir, clock, mut st, .. } =
+        fixture(1.0e9, &[("q1", None), ("q2", None), ("q3", None), ("q4", None)]);
+
+    // Learn q1 and q2, leaving q3 and q4 unlearned.
+    for _ in 0..2 {
+        let prob = st.get_new().unwrap().unwrap();
+        st.update(prob, 3).unwrap();
+    }
+    clock.advance(86400.0 * 30.0);
+
+    let nexts = st.get_nexts(2).unwrap();
+    let suspended = nexts[0].question.clone();
+    st.suspend(&nexts[0]).unwrap();
+    st.bury(&nexts[1]).unwrap();
+    let prob = st.get_new().unwrap().unwrap();
+    assert_eq!(prob.question, "q3");
+    st.suspend(&prob).unwrap();
+    assert_eq!(st.get_new().unwrap().unwrap().question, "q4");
+
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.unlearned, counts.suspended, counts.buried),
+               (0, 1, 2, 1));
+    let questions: Vec<_> = st.get_nexts(5)
+        .unwrap()
+        .into_iter()
+        .map(|p| p.question)
+        .collect();
+    assert_eq!(questions, vec!["q4 NEW"]);
+
+    // The buried problem comes back the next day.
+    clock.advance(86400.0);
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.suspended, counts.buried), (1, 2, 0));
+
+    st.unsuspend(&suspended).unwrap();
+    st.unsuspend("q3").unwrap();
+    assert!(st.unsuspend("missing").is_err());
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active, counts.unlearned, counts.suspended), (2, 2, 0));
+}
+
+// Syncing with a new problem list should keep the learning of the problems that remain.
+#[test]
+fn sync() {
+    let Fixture { tmp_dir: _tmp_dir, db_path, mut st, .. } =
+        fixture(1.0e9, &[("q1", None), ("q2", None), ("q3", None), ("q4", None), ("q5", None)]);
+    for _ in 0..5 {
+        let prob = st.get_new().unwrap().unwrap();
+        st.update(prob, 3).unwrap();
+    }
+
+    // Change q1, drop q4 and q5, and add q6.
+    let mut s = st.sync().unwrap();
+    s.add_problem("q1", "new answer").unwrap();
+    s.add_problem("q2", "answer").unwrap();
+    s.add_problem("q3", "answer").unwrap();
+    s.add_problem("q6", "answer").unwrap();
+    assert!(s.add_problem("q6", "answer").is_err());
+    drop(s);
+
+    let mut s = st.sync().unwrap();
+    for &(q, a) in &[("q1", "new answer"), ("q2", "answer"), ("q3", "answer"), ("q6", "answer")] {
+        s.add_problem(q, a).unwrap();
+    }
+    s.add_tags("q6", &["fresh"]).unwrap();
+    let report = s.finish(Missing::Retire).unwrap();
+    assert_eq!(report,
+               SyncReport {
+                   added: 1,
+                   updated: 1,
+                   unchanged: 2,
+                   retired: 2,
+                   ..SyncReport::default()
+               });
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.later, counts.unlearned, counts.suspended), (3, 1, 2));
+
+    // Profiles added later shouldn't be asked the retired problems either.
+    st.add_profile("kim").unwrap();
+    st.set_profile("kim").unwrap();
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.unlearned, counts.suspended), (4, 2));
+    st.set_profile(DEFAULT_PROFILE).unwrap();
+
+    // Bring back q4, and delete q5.  The tags of q6 are replaced.
+    let mut s = st.sync().unwrap();
+    for q in &["q1", "q2", "q3", "q4", "q6"] {
+        s.add_problem(q, "answer").unwrap();
+    }
+    s.add_tags("q6", &["stale"]).unwrap();
+    let report = s.finish(Missing::Delete).unwrap();
+    assert_eq!((report.updated, report.restored, report.deleted), (1, 1, 1));
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.later, counts.unlearned, counts.suspended), (4, 1, 0));
+    st.set_tag_filter(Some("fresh"));
+    assert_eq!(st.get_counts().unwrap().unlearned, 0);
+    st.set_tag_filter(Some("stale"));
+    assert_eq!(st.get_counts().unwrap().unlearned, 1);
+
+    let conn = Connection::open(&db_path).unwrap();
+    let logged: i64 = conn.query_row("SELECT COUNT(*) FROM log", &[], |row| row.get(0)).unwrap();
+    assert_eq!(logged, 4);
+}
+
+// Importing an export should recreate an identical store.
+#[test]
+fn export_import() {
+    let names: Vec<_> = (1..11).map(|i| format!("due {}", i)).collect();
+    let mut probs: Vec<_> = names.iter()
+        .map(|qn| (qn.as_str(), Some((1.0e9 - 20.0, 5.0))))
+        .collect();
+    probs.push(("later", Some((1.0e9 + 20.0, 5.0))));
+    probs.push(("new", None));
+    let Fixture { tmp_dir, mut st, .. } = fixture(1.0e9, &probs);
+    let copy_path = tmp_dir.path().join("copy.db");
+    {
+        let mut p = st.populate().unwrap();
+        p.add_tags("new", &["b", "a"]).unwrap();
+        p.commit().unwrap();
+    }
+    st.set_scheduler(Box::new(Sm2::default())).unwrap();
+    st.set_limits(Limits {
+            new_per_day: Some(5),
+            reviews_per_session: None,
+        })
+        .unwrap();
+    for _ in 0..8 {
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        st.update(prob, 3).unwrap();
+    }
+    let prob = st.get_nexts(2).unwrap().pop().unwrap();
+    st.suspend(&prob).unwrap();
+
+    let dump = st.export().unwrap();
+    assert_eq!(dump.log.len(), 8);
+    assert!(dump.problems.iter().any(|p| p.tags == vec!["a", "b"]));
+
+    let copy = Store::import(&copy_path, &dump).unwrap();
+    assert_eq!(copy.get_kind(), "test");
+    assert_eq!(copy.get_scheduler().name(), "sm2");
+    assert_eq!(copy.get_limits(), st.get_limits());
+    assert_eq!(copy.export().unwrap(), dump);
+}
+
+// Each profile should have its own learning state, suspensions and log over the same problems.
+#[test]
+fn profiles() {
+    let Fixture { tmp_dir, mut st, .. } = fixture(1.0e9, &[("q1", None), ("q2", None)]);
+    let copy_path = tmp_dir.path().join("copy.db");
+
+    assert_eq!(st.get_profile(), DEFAULT_PROFILE);
+    assert!(st.set_profile("kim").is_err());
+    st.add_profile("kim").unwrap();
+    assert_eq!(st.get_profiles().unwrap(), vec!["default", "kim"]);
+
+    // The default profile learns one problem, and suspends the other.
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+    let prob = st.get_new().unwrap().unwrap();
+    st.suspend(&prob).unwrap();
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.later, counts.unlearned, counts.suspended), (1, 0, 1));
+
+    // Kim hasn't learned anything yet.
+    st.set_profile("kim").unwrap();
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.later, counts.unlearned, counts.suspended), (0, 2, 0));
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+    assert_eq!(st.get_stats().unwrap().reviews, 1);
+
+    st.set_profile(DEFAULT_PROFILE).unwrap();
+    assert_eq!(st.get_stats().unwrap().reviews, 1);
+
+    // The profiles should survive an export and import.
+    let dump = st.export().unwrap();
+    assert_eq!(dump.profiles, vec!["default", "kim"]);
+    assert_eq!(dump.log.iter().filter(|ent| ent.profile == "kim").count(), 1);
+    let copy = Store::import(&copy_path, &dump).unwrap();
+    assert_eq!(copy.export().unwrap(), dump);
+}
+
+// Merging another copy should combine the logs, and recompute the problems that gained answers.
+#[test]
+fn merge() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+    let copy_path = tmp_dir.path().join("copy.db");
+
+    let clock = ManualClock::new(1.0e9);
+    let mut st = Store::create(&db_path, "test").unwrap();
+    st.set_clock(Box::new(clock.clone()));
+    {
+        let mut p = st.populate().unwrap();
+        p.add_problem("q1", "answer").unwrap();
+        p.add_problem("q2", "answer").unwrap();
+        p.commit().unwrap();
+    }
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+
+    // Both copies go on to answer different problems.
+    let mut copy = Store::import(&copy_path, &st.export().unwrap()).unwrap();
+    copy.set_clock(Box::new(clock.clone()));
+    {
+        let mut syncer = copy.sync().unwrap();
+        for q in &["q1", "q2", "q3"] {
+            syncer.add_problem(q, "answer").unwrap();
+        }
+        syncer.finish(Missing::Keep).unwrap();
+    }
+    clock.advance(60.0);
+    let prob = st.get_nexts(1).unwrap().pop().unwrap();
+    st.update(prob, 4).unwrap();
+    let prob = copy.get_new().unwrap().unwrap();
+    assert_eq!(prob.question, "q2");
+    copy.update(prob, 3).unwrap();
+
+    let report = st.merge(&copy.export().unwrap()).unwrap();
+    assert_eq!((report.problems_added, report.reviews_added, report.duplicates, report.replayed),
+               (1, 1, 1, 1));
+    assert_eq!(st.export().unwrap().log.len(), 3);
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.active + counts.later, counts.unlearned), (2, 1));
+
+    // Merging again changes nothing.
+    let report = st.merge(&copy.export().unwrap()).unwrap();
+    assert_eq!((report.problems_added, report.reviews_added, report.duplicates), (0, 0, 2));
+}
+
+// Replaying the log should reproduce the learning state, or show what another scheduler would do.
+#[test]
+fn rebuild() {
+    let Fixture { tmp_dir: _tmp_dir, clock, mut st, .. } =
+        fixture(1.0e9, &[("q1", None), ("q2", Some((2.0e9, 60.0)))]);
+    st.set_scheduler(Box::new(Sm2 { fuzz: 0.0, ..Sm2::default() })).unwrap();
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+    clock.advance(60.0);
+    let prob = st.get_nexts(1).unwrap().pop().unwrap();
+    assert_eq!(prob.question, "q1");
+    st.update(prob, 4).unwrap();
+
+    // Only answered problems are replayed, and nothing has changed.
+    let rebuilt = st.rebuild(false).unwrap();
+    assert_eq!(rebuilt.len(), 1);
+    assert_eq!(rebuilt[0].question, "q1");
+    assert!(!rebuilt[0].changed());
+
+    st.set_scheduler(Box::new(Sm2 { ease: 2.0, fuzz: 0.0, ..Sm2::default() })).unwrap();
+    let rebuilt = st.rebuild(false).unwrap();
+    assert!(rebuilt[0].changed());
+    assert!(rebuilt[0].replayed.1 < rebuilt[0].stored.unwrap().1);
+    assert_eq!(st.rebuild(false).unwrap(), rebuilt);
+
+    st.rebuild(true).unwrap();
+    let again = st.rebuild(false).unwrap();
+    assert!(!again[0].changed());
+    assert_eq!(again[0].stored, Some(rebuilt[0].replayed));
+}
+
+// Problems imported with learning state should only be replayed once they have been answered, and
+// then from the state they were imported with.
+#[test]
+fn rebuild_imported() {
+    let Fixture { tmp_dir: _tmp_dir, clock, mut st, .. } =
+        fixture(1.0e9, &[("q1", Some((1.0e9 + 60.0, 86400.0)))]);
+    st.set_scheduler(Box::new(Sm2 { fuzz: 0.0, ..Sm2::default() })).unwrap();
+    assert_eq!(st.rebuild(true).unwrap(), vec![]);
+    assert!(st.get_nexts(1).unwrap().is_empty());
+
+    clock.advance(60.0);
+    let prob = st.get_nexts(1).unwrap().pop().unwrap();
+    st.update(prob, 3).unwrap();
+
+    let rebuilt = st.rebuild(true).unwrap();
+    assert_eq!(rebuilt.len(), 1);
+    assert!(!rebuilt[0].changed());
+    assert!(rebuilt[0].replayed.1 > 86400.0);
+}
+
+// Fitting the scheduler to a log should improve its predictions, and only adjust its tunable
+// parameters.
+#[test]
+fn optimize() {
+    let names: Vec<_> = (0..10).map(|i| format!("q{}", i)).collect();
+    let probs: Vec<_> = names.iter().map(|qn| (qn.as_str(), None)).collect();
+    let Fixture { tmp_dir: _tmp_dir, clock, mut st, .. } = fixture(1.0e9, &probs);
+    st.set_scheduler(Box::new(Multiplier::default())).unwrap();
+
+    // Problems are remembered for an hour, and then forgotten.
+    let mut last = HashMap::new();
+    for _ in 0..50 {
+        while let Some(prob) = st.get_new().unwrap() {
+            last.insert(prob.question.clone(), clock.now());
+            st.update(prob, 3).unwrap();
+        }
+        for prob in st.get_nexts(10).unwrap() {
+            let grade = if clock.now() - last[&prob.question] < 3600.0 { 3 } else { 1 };
+            last.insert(prob.question.clone(), clock.now());
+            st.update(prob, grade).unwrap();
+        }
+        clock.advance(1200.0);
+    }
+
+    let fit = st.optimize().unwrap();
+    assert_eq!(fit.name, "multiplier");
+    assert_eq!(fit.old_params, Multiplier::default().params());
+    assert!(fit.reviews > 0);
+    assert!(fit.loss < fit.old_loss);
+    assert!(fit.params != fit.old_params);
+    assert_eq!(fit.params[4], fit.old_params[4]);
+
+    st.set_scheduler(from_config(&fit.name, &fit.params).unwrap()).unwrap();
+    assert_eq!(st.optimize().unwrap().old_loss, fit.loss);
+}
+
+// Only one session at a time should be able to open a database for writing, but others can read it.
+#[test]
+fn session_lock() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    let mut st = Store::create(&db_path, "test").unwrap();
+    populate(&mut st).unwrap();
+    match Store::open(&db_path) {
+        Err(Error::Locked(_)) => (),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Database opened by two sessions"),
+    }
+
+    let mut reader = Store::open_read_only(&db_path).unwrap();
+    assert_eq!(reader.get_kind(), "test");
+    let unlearned = reader.get_counts().unwrap().unlearned;
+    let prob = reader.get_new().unwrap().unwrap();
+    assert!(reader.update(prob, 3).is_err());
+
+    // The lock file stays, but no longer locks the database.
+    drop(st);
+    assert!(tmp_dir.path().join("learn.db-session").exists());
+    let mut st = Store::open(&db_path).unwrap();
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+    assert_eq!(reader.get_counts().unwrap().unlearned, unlearned - 1);
+
+    // A database that no session has open can be read too.
+    drop(reader);
+    drop(st);
+    let reader = Store::open_read_only(&db_path).unwrap();
+    assert_eq!(reader.get_counts().unwrap().unlearned, unlearned - 1);
+}
+
+// Only the configured number of backups should be kept, even when made within the same second,
+// and any of them can be restored.
+#[test]
+fn backups() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    let clock = ManualClock::new(1.0e9);
+    let mut st = Store::create(&db_path, "test").unwrap();
+    st.set_clock(Box::new(clock.clone()));
+    populate(&mut st).unwrap();
+    assert_eq!(Store::backups(&db_path).unwrap(), Vec::<PathBuf>::new());
+    st.set_backup_count(2).unwrap();
+
+    let mut made = vec![];
+    for _ in 0..3 {
+        let prob = st.get_new().unwrap().unwrap();
+        st.update(prob, 3).unwrap();
+        made.push(st.backup().unwrap());
+        clock.advance(60.0);
+    }
+    assert_eq!(made[0].file_name().unwrap(), "learn.db.20010909-014640-000.bak");
+    assert_eq!(Store::backups(&db_path).unwrap(), &made[1..]);
+
+    let again = st.backup().unwrap();
+    let last = st.backup().unwrap();
+    assert_eq!(last.file_name().unwrap(), "learn.db.20010909-014940-001.bak");
+    assert_eq!(Store::backups(&db_path).unwrap(), vec![again.clone(), last]);
+
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+    let unlearned = st.get_counts().unwrap().unlearned;
+
+    // The database can't be restored while it is open.
+    assert!(Store::restore(&db_path, &again).is_err());
+    drop(st);
+    let saved = Store::restore(&db_path, &again).unwrap();
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_counts().unwrap().unlearned, unlearned + 1);
+    assert_eq!(st.get_backup_count().unwrap(), 2);
+
+    // The contents that were replaced are backed up first, so the restore can be undone.
+    assert_eq!(Store::backups(&db_path).unwrap().last(), Some(&saved));
+    drop(st);
+    Store::restore(&db_path, &saved).unwrap();
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_counts().unwrap().unlearned, unlearned);
+}
+
+// Long intervals should come due at the start of a local day, and be counted by day.
+#[test]
+fn rollover() {
+    // 2001-09-09 08:00 UTC, which is 01:00 at UTC-7, before days start at 04:00.
+    let cur = 1000022400.0;
+    let day = 24.0 * 60.0 * 60.0;
+    let Fixture { tmp_dir: _tmp_dir, db_path, clock, mut st } =
+        fixture(cur,
+                &[("q0", Some((cur, 60.0))),
+                  ("q1", Some((cur, 1.5 * day))),
+                  ("q2", Some((cur, 2.2 * day))),
+                  ("q3", Some((cur + day, 60.0)))]);
+    let rollover = Rollover {
+        hour: 4,
+        utc_offset: -7 * 60,
+    };
+    st.set_rollover(rollover).unwrap();
+    assert!(st.set_rollover(Rollover { hour: 24, ..rollover }).is_err());
+    let day_start = cur - 21.0 * 3600.0;
+    assert_eq!(rollover.day_start(clock.now()), day_start);
+    assert_eq!(rollover.due(clock.now(), 60.0), clock.now() + 60.0);
+
+    let counts = st.get_counts().unwrap();
+    assert_eq!((counts.due_today, counts.due_tomorrow), (3, 1));
+
+    for prob in st.get_nexts(3).unwrap() {
+        st.update(prob, 3).unwrap();
+    }
+    drop(st);
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_rollover(), rollover);
+    for prob in st.export().unwrap().problems {
+        let learn = &prob.learning[0];
+        if prob.question == "q0" {
+            assert!(learn.interval < day);
+        } else if prob.question != "q3" {
+            assert!(learn.next > day_start + day);
+            assert_eq!(rollover.day_start(learn.next), learn.next);
+        }
+    }
+}
+
+// Problems should be counted in the configured buckets, which should persist.
+#[test]
+fn buckets() {
+    let cur = 1.0e9;
+    let intervals = [5.0, 30.0, 7200.0, 7200.0, 5.0e6];
+    let names: Vec<_> = (0..intervals.len()).map(|i| format!("q{}", i)).collect();
+    let probs: Vec<_> = names.iter()
+        .zip(&intervals)
+        .map(|(qn, &interval)| (qn.as_str(), Some((cur + interval, interval))))
+        .collect();
+    let Fixture { tmp_dir: _tmp_dir, db_path, mut st, .. } = fixture(cur, &probs);
+
+    let counts = st.get_counts().unwrap();
+    let buckets: Vec<_> = counts.buckets.iter().map(|b| (b.name.as_str(), b.count)).collect();
+    assert_eq!(buckets,
+               vec![("sec", 2), ("min", 0), ("hr", 2), ("day", 0), ("mon", 1)]);
+
+    assert!(st.set_buckets(vec![]).is_err());
+    assert!(st.set_buckets(vec![BucketSpec::new("a", 10.0, Stage::Active),
+                                BucketSpec::new("b", 10.0, Stage::Learned)])
+        .is_err());
+
+    st.set_buckets(vec![BucketSpec::new("young", 60.0, Stage::Active),
+                        BucketSpec::new("old", 86400.0, Stage::Learned)])
+        .unwrap();
+    drop(st);
+    let st = Store::open(&db_path).unwrap();
+    assert_eq!(st.get_buckets().len(), 2);
+    let counts = st.get_counts().unwrap();
+    let buckets: Vec<_> = counts.buckets
+        .iter()
+        .map(|b| (b.name.as_str(), b.count, b.stage))
+        .collect();
+    assert_eq!(buckets,
+               vec![("young", 2, Stage::Active), ("old", 3, Stage::Learned)]);
+}
+
+// Statistics should be computed from the log.
+#[test]
+fn stats() {
+    let Fixture { tmp_dir: _tmp_dir, clock, mut st, .. } =
+        fixture(1.0e9, &[("q1", None), ("q2", None)]);
+
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 3).unwrap();
+    for &(advance, grade) in &[(60.0, 1), (2.0 * 86400.0, 3)] {
+        clock.advance(advance);
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        st.update(prob, grade).unwrap();
+    }
+    let prob = st.get_new().unwrap().unwrap();
+    st.update(prob, 2).unwrap();
+    clock.advance(40.0 * 86400.0);
+    let prob = st.get_nexts(2)
+        .unwrap()
+        .into_iter()
+        .find(|p| p.question == "q1")
+        .unwrap();
+    st.update(prob, 4).unwrap();
+
+    let stats = st.get_stats().unwrap();
+    assert_eq!(stats.reviews, 5);
+    assert_eq!(stats.average_grade, Some(2.6));
+    let retention: Vec<_> = stats.retention
+        .iter()
+        .map(|r| (r.name.as_str(), r.reviews, r.recalled))
+        .collect();
+    assert_eq!(retention,
+               vec![("sec", 1, 0), ("min", 0, 0), ("hr", 0, 0), ("day", 1, 1), ("mon", 1, 1)]);
+    let days: Vec<_> = stats.days.iter().map(|d| (d.reviews, d.new)).collect();
+    assert_eq!(days, vec![(2, 1), (2, 1), (1, 0)]);
+    assert_eq!(stats.maturity.matured, 1);
+    assert_eq!(stats.maturity.median_time, Some(60.0 + 42.0 * 86400.0));
+    assert_eq!(stats.fails.len(), 1);
+    assert_eq!((stats.fails[0].question.as_str(), stats.fails[0].fails), ("q1", 1));
+}
+
+// The forecast should group the problems by when they are due.
+#[test]
+fn forecast() {
+    // Start at noon.
+    let start = 1.0e9 - 1.0e9 % 86400.0;
+    let times = [-100.0, 30.0, 3600.0, 5000.0, 86400.0, 2.0 * 86400.0, 10.0 * 86400.0];
+    let names: Vec<_> = (0..times.len()).map(|i| format!("q{}", i)).collect();
+    let probs: Vec<_> = names.iter()
+        .zip(&times)
+        .map(|(qn, time)| (qn.as_str(), Some((start + 43200.0 + time, 60.0))))
+        .collect();
+    let Fixture { tmp_dir: _tmp_dir, st, .. } = fixture(start + 43200.0, &probs);
+
+    let fc = st.get_forecast(Granularity::Days, 3).unwrap();
+    assert_eq!(fc.overdue, 1);
+    let counts: Vec<_> = fc.periods.iter().map(|p| p.count).collect();
+    assert_eq!(counts, vec![3, 1, 1]);
+    assert_eq!(fc.periods[1].start, start + 86400.0);
+
+    let fc = st.get_forecast(Granularity::Hours, 3).unwrap();
+    let counts: Vec<_> = fc.periods.iter().map(|p| p.count).collect();
+    assert_eq!(counts, vec![1, 2, 0]);
+}
+
+// Databases with the original schema should be upgraded when opened, keeping a backup.
+#[test]
+fn upgrade() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    create_old(&db_path, "20170704A");
+    {
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch("
+            INSERT INTO probs VALUES (2, 'learned', 'answer');
+            INSERT INTO learning VALUES (2, 2.0e9, 3600.0);")
+            .unwrap();
+    }
+
+    {
+        let mut st = Store::open(&db_path).unwrap();
+        assert_eq!(st.get_kind(), "test");
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        st.update(prob, 4).unwrap();
+    }
+
+    let version = |path| {
+        let conn = Connection::open(path).unwrap();
+        conn.query_row("SELECT version FROM schema_version", &[], |row| {
+            let vers: String = row.get(0);
+            vers
+        }).unwrap()
+    };
+    assert_eq!(version(db_path.clone()), SCHEMA_VERSION);
+    assert_eq!(version(tmp_dir.path().join("learn.db.20170704A.bak")), "20170704A");
+
+    // Merging and replaying look up the answers to a problem, which shouldn't scan the log.
+    {
+        let conn = Connection::open(&db_path).unwrap();
+        let plan: String = conn.query_row("
+            EXPLAIN QUERY PLAN
+            SELECT COUNT(*) FROM log
+            WHERE profile = 'default' AND probid = 1 AND stamp = 0.0 AND score = 4",
+                       &[],
+                       |row| row.get(3))
+            .unwrap();
+        assert!(plan.contains("log_problem"), "{}", plan);
+    }
+
+    // Problems that were learned without a log must have been imported.
+    {
+        let conn = Connection::open(&db_path).unwrap();
+        let next: f64 = conn.query_row("SELECT next FROM imported WHERE probid = 2",
+                                       &[],
+                                       |row| row.get(0))
+            .unwrap();
+        assert_eq!(next, 2.0e9);
+    }
+
+    // A database from the future should be refused.
+    {
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute("UPDATE schema_version SET version = '29990101A'", &[]).unwrap();
+    }
+    assert!(Store::open(&db_path).is_err());
+}
+
+// The log of databases from before the log was written correctly should be repaired, leaving the
+// rows that were already right alone.
+#[test]
+fn log_repair() {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    create_old(&db_path, "20170709A");
+    {
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch("
+            INSERT INTO probs VALUES (100, 'question 100', 'answer 100');
+            INSERT INTO log VALUES (1.0, 100, 2);
+            INSERT INTO log VALUES (2.0, 4, 1);
+            INSERT INTO log VALUES (3.0, 1, 100);")
+            .unwrap();
+    }
+
+    {
+        let mut st = Store::open(&db_path).unwrap();
+        let prob = st.get_nexts(1).unwrap().pop().unwrap();
+        st.update(prob, 3).unwrap();
+    }
+
+    let conn = Connection::open(&db_path).unwrap();
+    let mut stmt = conn.prepare("SELECT score, probid FROM log ORDER BY stamp").unwrap();
+    let rows: Vec<(i64, i64)> = stmt.query_map(&[], |row| (row.get(0), row.get(1)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(rows, vec![(2, 100), (4, 1), (1, 100), (3, 1)]);
+}
+
+// Create a database using one of the older schemas, with a single problem.
+fn create_old(path: &Path, version: &str) {
+    let conn = Connection::open(path).unwrap();
+    conn.execute_batch("
+        CREATE TABLE probs (id INTEGER PRIMARY KEY,
+            question TEXT UNIQUE,
+            answer TEXT NOT NULL);
+        CREATE TABLE learning (probid INTEGER PRIMARY KEY REFERENCES probs (id),
+            next REAL NOT NULL,
+            interval REAL NOT NULL);
+        CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+        INSERT INTO config VALUES ('kind', 'test');
+        CREATE INDEX learning_next ON learning (next);
+        CREATE TABLE schema_version (version TEXT NOT NULL);
+        INSERT INTO probs VALUES (1, 'question', 'answer');")
+        .unwrap();
+    if version != "20170704A" {
+        conn.execute_batch("
+            CREATE TABLE log (stamp REAL NOT NULL,
+                score INTEGER NOT NULL,
+                probid INTEGER REFERENCES probs (id) NOT NULL);")
+            .unwrap();
+    }
+    conn.execute("INSERT INTO schema_version VALUES (?)", &[&version]).unwrap();
+}
+
+// A store in a temporary directory, on a manual clock.
+struct Fixture {
+    tmp_dir: TempDir,
+    db_path: PathBuf,
+    clock: ManualClock,
+    st: Store,
+}
+
+// Create a store of kind "test" in a new temporary directory, on a manual clock starting at
+// `start`, with the given problems.  Problems given a next time and interval are already being
+// learned.  The directory is removed when `tmp_dir` is dropped, so it must be kept.
+fn fixture(start: f64, probs: &[(&str, Option<(f64, f64)>)]) -> Fixture {
+    let tmp_dir = TempDir::new("learn").unwrap();
+    let db_path = tmp_dir.path().join("learn.db");
+
+    let clock = ManualClock::new(start);
+    let mut st = Store::create(&db_path, "test").unwrap();
+    st.set_clock(Box::new(clock.clone()));
+    {
+        let mut p = st.populate().unwrap();
+        for &(question, ni) in probs {
+            match ni {
+                None => p.add_problem(question, "answer").unwrap(),
+                Some((next, interval)) => {
+                    p.add_learning_problem(question, "answer", next, interval).unwrap()
+                }
+            }
+        }
+        p.commit().unwrap();
+    }
+
+    Fixture {
+        tmp_dir: tmp_dir,
+        db_path: db_path,
+        clock: clock,
+        st: st,
+    }
+}
+
 // Populate with test data.  Make a mix of unlearned problems, and learned ones that are ready to
 // learn, and those that aren't ready to learn.
 fn populate(st: &mut Store) -> Result<()> {